@@ -0,0 +1,174 @@
+//! Compares the old way of handling collisions, scanning every asteroid and laser for
+//! each contact, with the game's `dispatch_collisions`, which looks the two entities
+//! of a contact up directly. Both run on a field of pooled asteroids and lasers, as
+//! the game sets them up, and are fed the same collision events.
+//!
+//! Run with `cargo bench --bench collision`
+
+use asteroids::collision::{dispatch_collisions, AsteroidHit, ShipHit, UfoHit};
+use asteroids::components::*;
+use asteroids::pool::{Pool, PoolPlugin};
+use asteroids::resources::{GameMode, GodMode};
+use asteroids::settings::Settings;
+use asteroids::shape_cache::ShapeCachePlugin;
+use asteroids::wrap::owner_of;
+use bevy::asset::AssetPlugin;
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::geometry::CollisionEventFlags;
+use std::time::{Duration, Instant};
+
+/// contacts reported in a frame, a busy one with every laser hitting something
+const CONTACTS: usize = 16;
+const LASERS: usize = 16;
+const FRAMES: u32 = 200;
+
+/// The collision handling from before `dispatch_collisions`, minus the despawning:
+/// for each contact, every asteroid against every laser
+fn scan_collisions(
+    mut collision_events: EventReader<CollisionEvent>,
+    laser_query: Query<(Entity, &Laser)>,
+    asteroid_query: Query<Entity, With<Asteroid>>,
+    ghosts: Query<&Ghost>,
+    mut asteroid_hits: EventWriter<AsteroidHit>,
+) {
+    let mut processed_lasers = Vec::new();
+    let mut destroyed = Vec::new();
+    for event in collision_events.iter() {
+        if let CollisionEvent::Started(h1, h2, _flags) = event {
+            let (h1, h2) = (owner_of(*h1, &ghosts), owner_of(*h2, &ghosts));
+            for asteroid in asteroid_query.iter() {
+                for (laser, owner) in laser_query.iter() {
+                    if (h1 == asteroid && h2 == laser) || (h1 == laser && h2 == asteroid) {
+                        if processed_lasers.contains(&laser) || destroyed.contains(&asteroid) {
+                            continue;
+                        }
+                        processed_lasers.push(laser);
+                        destroyed.push(asteroid);
+                        asteroid_hits.send(AsteroidHit {
+                            asteroid,
+                            laser,
+                            by: owner.owner,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A field of `asteroids` rocks and `LASERS` lasers out of the pool, the contacts of
+/// one frame and how many hits they make
+fn setup(asteroids: usize) -> (App, Vec<CollisionEvent>, usize) {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<Mesh>()
+        .insert_resource(Settings::default())
+        .insert_resource(GameMode::default())
+        .insert_resource(GodMode::default())
+        .add_event::<CollisionEvent>()
+        .add_event::<AsteroidHit>()
+        .add_event::<UfoHit>()
+        .add_event::<ShipHit>()
+        .add_plugin(ShapeCachePlugin)
+        .add_plugin(PoolPlugin);
+    // fill the pool
+    app.update();
+
+    let world = &mut app.world;
+    let mut state: SystemState<(Commands, ResMut<Pool>)> = SystemState::new(world);
+    let (mut commands, mut pool) = state.get_mut(world);
+    for _ in 0..asteroids {
+        pool.launch_asteroid(
+            &mut commands,
+            AsteroidSize::Small,
+            Transform::default(),
+            Velocity::zero(),
+        );
+    }
+    for _ in 0..LASERS {
+        pool.fire_laser(&mut commands, Player(0), Transform::default(), Vec2::ZERO);
+    }
+    state.apply(world);
+
+    let rocks: Vec<Entity> = world
+        .query_filtered::<Entity, With<Asteroid>>()
+        .iter(world)
+        .collect();
+    let lasers: Vec<Entity> = world
+        .query_filtered::<Entity, With<Laser>>()
+        .iter(world)
+        .collect();
+    assert_eq!((rocks.len(), lasers.len()), (asteroids, LASERS));
+
+    // spread the hits over the field so none are found early by accident. A small
+    // field gets some rocks hit twice, which only counts once
+    let targets: Vec<Entity> = (0..CONTACTS)
+        .map(|i| rocks[(i * 7919 + asteroids / 2) % asteroids])
+        .collect();
+    let hits = targets.iter().collect::<HashSet<_>>().len();
+    let contacts = targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, rock)| {
+            let laser = lasers[i % LASERS];
+            let (h1, h2) = if i % 2 == 0 {
+                (rock, laser)
+            } else {
+                (laser, rock)
+            };
+            CollisionEvent::Started(h1, h2, CollisionEventFlags::empty())
+        })
+        .collect();
+    (app, contacts, hits)
+}
+
+/// average time `system` takes to handle one frame's contacts
+fn time<Params>(
+    world: &mut World,
+    contacts: &[CollisionEvent],
+    expected_hits: usize,
+    system: impl IntoSystem<(), (), Params>,
+) -> Duration {
+    let mut system = IntoSystem::into_system(system);
+    system.initialize(world);
+    let mut elapsed = Duration::ZERO;
+    for frame in 0..FRAMES {
+        world
+            .resource_mut::<Events<CollisionEvent>>()
+            .extend(contacts.iter().cloned());
+        let start = Instant::now();
+        system.run((), world);
+        elapsed += start.elapsed();
+
+        let mut hits = world.resource_mut::<Events<AsteroidHit>>();
+        if frame == 0 {
+            assert_eq!(hits.len(), expected_hits);
+        }
+        hits.clear();
+        world.resource_mut::<Events<CollisionEvent>>().clear();
+    }
+    elapsed / FRAMES
+}
+
+fn main() {
+    println!(
+        "{:>9} {:>14} {:>14} {:>9}",
+        "asteroids", "scan", "dispatch", "speedup"
+    );
+    for asteroids in [10, 100, 1_000, 5_000] {
+        let (mut app, contacts, hits) = setup(asteroids);
+        let scan = time(&mut app.world, &contacts, hits, scan_collisions);
+        let dispatch = time(&mut app.world, &contacts, hits, dispatch_collisions);
+        println!(
+            "{:>9} {:>14?} {:>14?} {:>8.0}x",
+            asteroids,
+            scan,
+            dispatch,
+            scan.as_secs_f64() / dispatch.as_secs_f64()
+        );
+    }
+}
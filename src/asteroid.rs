@@ -1,197 +1,657 @@
+use crate::collision::{AsteroidHit, DispatchCollisions};
 use crate::components::*;
+use crate::console::{self, AddConsoleCommand, CommandError};
+use crate::daily::{DailyRun, Modifier, SeedRun};
+use crate::difficulty::GameDifficulty;
+use crate::events::{AsteroidDestroyed, LevelCleared};
+use crate::pool::{self, Pool};
 use crate::resources::*;
+use crate::savegame::PendingRun;
+use crate::settings::Difficulty;
+use crate::ship::heading;
+use bevy::ecs::system::SystemState;
 use bevy::prelude::*;
-use bevy_prototype_lyon::prelude::{FillMode, *};
+use bevy_prototype_lyon::prelude::*;
 use bevy_rapier2d::prelude::*;
-use rand::rngs::ThreadRng;
 use rand::*;
 
 pub struct AsteroidPlugin;
 
 impl Plugin for AsteroidPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_asteroids))
+        app.init_resource::<DeferredSpawns>()
             .add_system_set(
-                SystemSet::on_update(GameState::Playing).with_system(check_for_laser_collision),
+                SystemSet::on_enter(GameState::Playing).with_system(spawn_asteroids.after(SeedRun)),
             )
-            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_asteroids));
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    // in the same frame, while the destroyed asteroids are still around
+                    .with_system(check_level_cleared.after(destroy_asteroids))
+                    .with_system(spawn_next_wave.after(check_level_cleared)),
+            );
+
+        // the attract mode demo on the title screen shoots asteroids too
+        for state in [GameState::Title, GameState::Playing] {
+            app.add_system_set(
+                SystemSet::on_update(state)
+                    .with_system(destroy_asteroids.after(DispatchCollisions))
+                    .with_system(split_asteroids.after(destroy_asteroids))
+                    .with_system(spawn_deferred),
+            )
+            .add_system_set(SystemSet::on_exit(state).with_system(remove_asteroids));
+        }
+
+        app.add_console_command("spawn", "<small|medium|large> [count]", spawn)
+            .add_console_command("clear", "", clear)
+            .add_console_command("level", "<number>", jump_to_level);
     }
 }
 
-fn spawn_asteroids(mut commands: Commands, level: ResMut<Level>) {
-    // determine a random starting velocity
-    let mut rng = rand::thread_rng();
-    let count = 1 + level.0;
+/// how far from the ship a new asteroid has to appear
+pub const SHIP_CLEARANCE: f32 = 250.0;
+/// the asteroids in each wave of a versus match, there to get in the way
+const VERSUS_WAVE: u32 = 3;
+/// the asteroids a survival game starts with
+const SURVIVAL_WAVE: u32 = 3;
+/// the extra asteroids in each wave with `Modifier::Swarm`
+const SWARM: u32 = 2;
+/// how many random spots are tried before giving up on a spawn until the next frame
+const SPAWN_ATTEMPTS: usize = 32;
+/// the most asteroids the console spawns at once
+const MAX_SPAWN: u32 = 100;
+/// how fast asteroids turn, in radians a second
+pub const ASTEROID_SPIN: f32 = 0.4;
+/// the smaller asteroids a destroyed one breaks into
+pub const FRAGMENTS: u32 = 4;
 
-    for _ in 0..count {
-        create_asteroid(&mut commands, &mut rng, AsteroidSize::Large, None);
+#[allow(clippy::too_many_arguments)]
+fn spawn_asteroids(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    mode: Res<GameMode>,
+    level: ResMut<Level>,
+    arena: Res<Arena>,
+    rng: Res<GameRng>,
+    daily: Res<DailyRun>,
+    pending: Res<PendingRun>,
+    mut deferred: ResMut<DeferredSpawns>,
+    ship_query: Query<&Transform, With<Ship>>,
+    asteroid_query: Query<(&Asteroid, &Transform)>,
+) {
+    // a continued game brings its own field
+    if pending.0.is_some() {
+        return;
     }
+    spawn_into_field(
+        &mut commands,
+        &mut pool,
+        difficulty.0,
+        &mut rng.wave(level.0),
+        &arena,
+        &ship_query,
+        &asteroid_query,
+        &mut deferred,
+        AsteroidSize::Large,
+        wave_size(&mode, level.0, &daily),
+    );
 }
 
-fn create_asteroid(
+/// how many large asteroids the wave of `level` starts with
+pub fn wave_size(mode: &GameMode, level: u32, daily: &DailyRun) -> u32 {
+    let size = match mode {
+        // a versus field stays sparse; the rocks are obstacles, not the opponent
+        GameMode::Versus => VERSUS_WAVE,
+        // the rest stream in as the game goes on
+        GameMode::Survival => SURVIVAL_WAVE,
+        // asteroids are put in by hand
+        GameMode::Sandbox => 0,
+        _ => 1 + level,
+    };
+    if daily.has(Modifier::Swarm) {
+        size + SWARM
+    } else {
+        size
+    }
+}
+
+/// Spawn `count` asteroids of `size` around the edges, clear of the ship and of the
+/// asteroids already on the field
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_into_field(
     commands: &mut Commands,
-    rng: &mut ThreadRng,
+    pool: &mut Pool,
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+    arena: &Arena,
+    ship_query: &Query<&Transform, With<Ship>>,
+    asteroid_query: &Query<(&Asteroid, &Transform)>,
+    deferred: &mut DeferredSpawns,
     size: AsteroidSize,
-    position: Option<Vec3>,
+    count: u32,
 ) {
-    let vx = rng.gen_range(-1.0..1.0);
-    let vy = rng.gen_range(-1.0..1.0);
+    let mut area = SpawnArea::from_queries(arena, ship_query, asteroid_query.iter());
+    spawn_wave(
+        commands, pool, difficulty, rng, &mut area, deferred, size, count,
+    );
+}
 
-    // determine a random starting position outside the screen
-    let x = if rng.gen_bool(0.5) {
-        rng.gen_range(-1000.0..-100.0)
-    } else {
-        rng.gen_range(100.0..1000.0)
-    };
+/// Asteroids there was no free spot for yet, tried again every frame until there is
+#[derive(Resource, Default)]
+pub struct DeferredSpawns(pub Vec<AsteroidSize>);
 
-    let y = if rng.gen_bool(0.5) {
-        rng.gen_range(-1000.0..-100.0)
-    } else {
-        rng.gen_range(100.0..1000.0)
-    };
+/// Where a new wave of asteroids may appear
+pub struct SpawnArea {
+    /// half the size of the arena
+    half_extents: Vec2,
+    ships: Vec<Vec2>,
+    /// asteroids already on the field (including ones placed by this wave) and their radius
+    occupied: Vec<(Vec2, f32)>,
+}
 
-    let dimension = match size {
+impl SpawnArea {
+    /// An arena of `half_extents` with the ships and asteroids (their size and where
+    /// they are) that are in it
+    pub fn new(
+        half_extents: Vec2,
+        ships: impl Iterator<Item = Vec2>,
+        asteroids: impl Iterator<Item = (AsteroidSize, Vec2)>,
+    ) -> Self {
+        Self {
+            half_extents,
+            ships: ships.collect(),
+            occupied: asteroids
+                .map(|(size, position)| (position, radius(size)))
+                .collect(),
+        }
+    }
+
+    fn from_queries<'a>(
+        arena: &Arena,
+        ship_query: &Query<&Transform, With<Ship>>,
+        asteroids: impl Iterator<Item = (&'a Asteroid, &'a Transform)>,
+    ) -> Self {
+        Self::new(
+            arena.half_extents(),
+            ship_query
+                .iter()
+                .map(|transform| transform.translation.truncate()),
+            asteroids.map(|(asteroid, transform)| (asteroid.0, transform.translation.truncate())),
+        )
+    }
+
+    /// Where an asteroid of `size` can go, or None after adding it to `deferred` when
+    /// there is no room for it yet
+    pub fn place(
+        &mut self,
+        rng: &mut impl Rng,
+        size: AsteroidSize,
+        deferred: &mut Vec<AsteroidSize>,
+    ) -> Option<Vec2> {
+        let position = self.find_position(rng, radius(size));
+        if position.is_none() {
+            deferred.push(size);
+        }
+        position
+    }
+
+    /// A spot just past a random edge of the screen, far enough from the ship and not
+    /// on top of another asteroid. None when the edges are too crowded to find one
+    fn find_position(&mut self, rng: &mut impl Rng, radius: f32) -> Option<Vec2> {
+        let size = self.half_extents * 2.0;
+        // a ship that is about to (re)spawn will appear in the middle
+        let ships = if self.ships.is_empty() {
+            &[Vec2::ZERO][..]
+        } else {
+            &self.ships
+        };
+        let position = (0..SPAWN_ATTEMPTS)
+            .map(|_| self.edge_point(rng, radius))
+            .find(|&candidate| {
+                ships
+                    .iter()
+                    .all(|&ship| wrapped_distance(candidate, ship, size) - radius >= SHIP_CLEARANCE)
+                    && self.occupied.iter().all(|&(position, r)| {
+                        wrapped_distance(position, candidate, size) >= r + radius
+                    })
+            })?;
+        self.occupied.push((position, radius));
+        Some(position)
+    }
+
+    /// a random point just out of sight past one of the edges
+    fn edge_point(&self, rng: &mut impl Rng, radius: f32) -> Vec2 {
+        let Vec2 { x: w, y: h } = self.half_extents;
+        // weigh the sides by their length so spawns are spread evenly around the border
+        let t = rng.gen_range(0.0..(w + h) * 2.0);
+        let sign = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+        if t < w * 2.0 {
+            Vec2::new(t - w, sign * (h + radius))
+        } else {
+            Vec2::new(sign * (w + radius), t - w * 2.0 - h)
+        }
+    }
+}
+
+/// Distance between two points on a playfield that wraps around
+fn wrapped_distance(a: Vec2, b: Vec2, size: Vec2) -> f32 {
+    let delta = (a - b).abs();
+    delta.min(size - delta).length()
+}
+
+/// Spawn `count` asteroids of `size` around the edges of the screen. The ones there
+/// is no room for yet are deferred
+#[allow(clippy::too_many_arguments)]
+fn spawn_wave(
+    commands: &mut Commands,
+    pool: &mut Pool,
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+    area: &mut SpawnArea,
+    deferred: &mut DeferredSpawns,
+    size: AsteroidSize,
+    count: u32,
+) {
+    for _ in 0..count {
+        if let Some(position) = area.place(rng, size, &mut deferred.0) {
+            create_asteroid(commands, pool, difficulty, rng, size, position.extend(0.0));
+        }
+    }
+}
+
+/// Try again to place the asteroids that found no room before, now the field has
+/// moved on. Whatever still doesn't fit waits for the next frame
+#[allow(clippy::too_many_arguments)]
+fn spawn_deferred(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    arena: Res<Arena>,
+    mut rng: ResMut<GameRng>,
+    mut deferred: ResMut<DeferredSpawns>,
+    ship_query: Query<&Transform, With<Ship>>,
+    asteroid_query: Query<(&Asteroid, &Transform)>,
+) {
+    if deferred.0.is_empty() {
+        return;
+    }
+    let mut area = SpawnArea::from_queries(&arena, &ship_query, asteroid_query.iter());
+    let sizes = std::mem::take(&mut deferred.0);
+    for size in sizes {
+        spawn_wave(
+            &mut commands,
+            &mut pool,
+            difficulty.0,
+            &mut rng.rng,
+            &mut area,
+            &mut deferred,
+            size,
+            1,
+        );
+    }
+}
+
+/// the distance from the center of an asteroid to its corners
+pub fn radius(size: AsteroidSize) -> f32 {
+    match size {
         AsteroidSize::Large => 80.0,
         AsteroidSize::Medium => 60.0,
         AsteroidSize::Small => 20.0,
-        AsteroidSize::Dead => 0.0,
-    };
-    // determine origination position
-    let pos = match position {
-        Some(pos) => pos,
-        None => Vec3::new(x, y, 0.0),
-    };
+    }
+}
 
-    let octogon = create_shape(dimension);
-    commands
-        .spawn(GeometryBuilder::build_as(
-            &octogon,
-            DrawMode::Outlined {
-                fill_mode: FillMode {
-                    color: FILL_COLOR,
-                    options: FillOptions::default(),
-                },
-                outline_mode: StrokeMode {
-                    color: Color::WHITE,
-                    options: StrokeOptions::default(),
-                },
-            },
-            Transform {
-                translation: pos,
-                ..default()
-            },
-        ))
-        .insert(RigidBody::Dynamic)
-        .insert(GravityScale(0.0))
-        .insert(Collider::polyline(octogon.points, None))
-        .insert(ActiveEvents::COLLISION_EVENTS)
-        .insert(Sensor)
-        .insert(Velocity {
-            linvel: Vec2::new(vx * 100.0, vy * 100.0),
-            angvel: 0.4,
-        })
-        .insert(Sleeping::disabled())
-        .insert(Ccd::enabled())
-        .insert(Wrapper)
-        .insert(Dimensions {
-            width: dimension,
-            height: dimension,
-        })
-        .insert(Asteroid(size));
-}
-
-fn create_shape(radius: f32) -> shapes::Polygon {
+pub fn create_asteroid(
+    commands: &mut Commands,
+    pool: &mut Pool,
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+    size: AsteroidSize,
+    position: Vec3,
+) {
+    pool.launch_asteroid(
+        commands,
+        size,
+        Transform::from_translation(position),
+        Velocity {
+            linvel: asteroid_velocity(rng, difficulty),
+            angvel: ASTEROID_SPIN,
+        },
+    );
+}
+
+/// a random direction, with a speed picked from the difficulty's range
+pub fn asteroid_velocity(rng: &mut impl Rng, difficulty: Difficulty) -> Vec2 {
+    let (min_speed, max_speed) = difficulty.preset().asteroid_speed;
+    heading(rng.gen_range(0.0..std::f32::consts::TAU)) * rng.gen_range(min_speed..max_speed)
+}
+
+/// what an asteroid of `size` breaks into, if anything
+pub fn fragment(size: AsteroidSize) -> Option<AsteroidSize> {
+    match size {
+        AsteroidSize::Large => Some(AsteroidSize::Medium),
+        AsteroidSize::Medium => Some(AsteroidSize::Small),
+        AsteroidSize::Small => None,
+    }
+}
+
+/// the outline of an asteroid of `size`, which is what gets hit
+pub fn asteroid_collider(size: AsteroidSize) -> Collider {
+    Collider::polyline(create_shape(radius(size)).points, None)
+}
+
+pub fn create_shape(radius: f32) -> shapes::Polygon {
     // create octogon shape
     let mut shape_points = Vec::new();
     let mut angle: f32 = 0.0;
     for _ in 0..8 {
-        let x = angle.cos() * radius;
-        let y = angle.sin() * radius;
-        shape_points.push(Vec2::new(x, y));
+        shape_points.push(heading(angle) * radius);
         angle += std::f32::consts::PI / 4.0;
     }
 
     shapes::Polygon {
         points: shape_points.to_vec(),
         closed: true,
-        ..Default::default()
     }
 }
 
-fn remove_asteroids(mut commands: Commands, query: Query<Entity, With<Asteroid>>) {
+fn remove_asteroids(
+    mut commands: Commands,
+    mut deferred: ResMut<DeferredSpawns>,
+    query: Query<Entity, With<Asteroid>>,
+) {
     for entity in query.iter() {
-        commands.entity(entity).despawn_recursive();
+        pool::release(&mut commands, entity);
     }
+    deferred.0.clear();
 }
 
-fn check_for_laser_collision(
+/// Destroy any asteroid hit by one of the players' lasers
+fn destroy_asteroids(
     mut commands: Commands,
-    mut collision_events: EventReader<CollisionEvent>,
+    mut hits: EventReader<AsteroidHit>,
+    asteroid_query: Query<(&Asteroid, &Transform, &Velocity)>,
+    mut destroyed_events: EventWriter<AsteroidDestroyed>,
+) {
+    for hit in hits.iter() {
+        let Ok((asteroid, transform, velocity)) = asteroid_query.get(hit.asteroid) else {
+            continue;
+        };
+        pool::release(&mut commands, hit.laser);
+        pool::release(&mut commands, hit.asteroid);
+        destroyed_events.send(AsteroidDestroyed {
+            size: asteroid.0,
+            position: transform.translation.truncate(),
+            velocity: velocity.linvel,
+            by: hit.by,
+        });
+    }
+}
+
+/// spawn `FRAGMENTS` smaller asteroids that fly in different directions
+fn split_asteroids(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    mut rng: ResMut<GameRng>,
+    mut destroyed_events: EventReader<AsteroidDestroyed>,
+) {
+    for destroyed in destroyed_events.iter() {
+        let Some(size) = fragment(destroyed.size) else {
+            continue;
+        };
+        for _ in 0..FRAGMENTS {
+            let position = destroyed.position.extend(0.0);
+            create_asteroid(
+                &mut commands,
+                &mut pool,
+                difficulty.0,
+                &mut rng.rng,
+                size,
+                position,
+            );
+        }
+    }
+}
+
+/// Move on to the next level once the last asteroid is destroyed, and none are still
+/// waiting for room to spawn
+fn check_level_cleared(
     mut level: ResMut<Level>,
-    laser_query: Query<Entity, With<Laser>>,
-    asteroid_query: Query<(Entity, &Asteroid, &Transform)>,
+    deferred: Res<DeferredSpawns>,
+    mut destroyed_events: EventReader<AsteroidDestroyed>,
+    mut cleared_events: EventWriter<LevelCleared>,
+    asteroid_query: Query<(), With<Asteroid>>,
+) {
+    // despawning and spawning are deferred, so destroyed asteroids still show up in
+    // the query and their fragments don't yet
+    let mut destroyed = 0;
+    let mut splitting = false;
+    for event in destroyed_events.iter() {
+        destroyed += 1;
+        splitting |= fragment(event.size).is_some();
+    }
+    if destroyed > 0
+        && !splitting
+        && deferred.0.is_empty()
+        && asteroid_query.iter().count() == destroyed
+    {
+        cleared_events.send(LevelCleared);
+        level.0 += 1;
+    }
+}
+
+/// The field is empty so only the ship needs to be kept clear of
+#[allow(clippy::too_many_arguments)]
+fn spawn_next_wave(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    mode: Res<GameMode>,
+    level: Res<Level>,
+    arena: Res<Arena>,
+    rng: Res<GameRng>,
+    daily: Res<DailyRun>,
+    mut deferred: ResMut<DeferredSpawns>,
+    ship_query: Query<&Transform, With<Ship>>,
+    mut cleared_events: EventReader<LevelCleared>,
+) {
+    for _ in cleared_events.iter() {
+        spawn_fresh_wave(
+            &mut commands,
+            &mut pool,
+            difficulty.0,
+            &mut rng.wave(level.0),
+            &arena,
+            &ship_query,
+            &mut deferred,
+            wave_size(&mode, level.0, &daily),
+        );
+    }
+}
+
+/// Spawn `count` large asteroids around the edges of an empty field
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_fresh_wave(
+    commands: &mut Commands,
+    pool: &mut Pool,
+    difficulty: Difficulty,
+    rng: &mut impl Rng,
+    arena: &Arena,
+    ship_query: &Query<&Transform, With<Ship>>,
+    deferred: &mut DeferredSpawns,
+    count: u32,
 ) {
-    // It is possible for a single laser to collide with multiple asteroids at once.
-    // We need to keep track of each so we don't try removing it multiple times
-    let mut processed_lasers = Vec::new();
-    for event in collision_events.iter() {
-        if let CollisionEvent::Started(h1, h2, _flags) = event {
-            for (asteroid_entity, asteroid, transform) in asteroid_query.iter() {
-                for laser in laser_query.iter() {
-                    if (h1.to_owned() == asteroid_entity && h2.to_owned() == laser)
-                        || (h1.to_owned() == laser && h2.to_owned() == asteroid_entity)
-                    {
-                        if processed_lasers.contains(&laser) {
-                            continue;
-                        }
-
-                        commands.entity(laser).despawn();
-                        commands.entity(asteroid_entity).despawn();
-
-                        processed_lasers.push(laser);
-
-                        // spawn 4 smaller asteroids that fly in different directions
-                        let size = match asteroid.0 {
-                            AsteroidSize::Large => AsteroidSize::Medium,
-                            AsteroidSize::Medium => AsteroidSize::Small,
-                            AsteroidSize::Small => AsteroidSize::Dead,
-                            AsteroidSize::Dead => AsteroidSize::Dead,
-                        };
-
-                        let mut rng = rand::thread_rng();
-                        if size != AsteroidSize::Dead {
-                            // get destroyed asteroid's position
-                            let position = transform.translation;
-                            for _ in 0..4 {
-                                create_asteroid(&mut commands, &mut rng, size, Some(position));
-                            }
-                        } else {
-                            // get total asteroid count
-                            let mut count = 0;
-                            for _ in asteroid_query.iter() {
-                                count += 1;
-                            }
-
-                            // we check for one because the one we just destroyed will still be in
-                            // the list
-                            if count == 1 {
-                                level.0 += 1;
-
-                                let count = 1 + level.0;
-
-                                for _ in 0..count {
-                                    create_asteroid(
-                                        &mut commands,
-                                        &mut rng,
-                                        AsteroidSize::Large,
-                                        None,
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
+    let mut area = SpawnArea::from_queries(arena, ship_query, std::iter::empty());
+    spawn_wave(
+        commands,
+        pool,
+        difficulty,
+        rng,
+        &mut area,
+        deferred,
+        AsteroidSize::Large,
+        count,
+    );
+}
+
+/// Spawn asteroids around the edges, as a wave would
+#[allow(clippy::type_complexity)]
+fn spawn(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+    console::playing(world)?;
+    let size = match args.first().copied() {
+        Some("small") => AsteroidSize::Small,
+        Some("medium") => AsteroidSize::Medium,
+        Some("large") => AsteroidSize::Large,
+        _ => return Err(CommandError::Usage),
+    };
+    let count = if args.len() > 1 {
+        console::arg(args, 1)?
+    } else {
+        1
+    };
+    if count > MAX_SPAWN {
+        return Err(CommandError::Failed(format!(
+            "at most {} at once",
+            MAX_SPAWN
+        )));
+    }
+
+    let mut state: SystemState<(
+        Commands,
+        ResMut<Pool>,
+        Res<GameDifficulty>,
+        ResMut<GameRng>,
+        Res<Arena>,
+        ResMut<DeferredSpawns>,
+        Query<&Transform, With<Ship>>,
+        Query<(&Asteroid, &Transform)>,
+    )> = SystemState::new(world);
+    let (
+        mut commands,
+        mut pool,
+        difficulty,
+        mut rng,
+        arena,
+        mut deferred,
+        ship_query,
+        asteroid_query,
+    ) = state.get_mut(world);
+    spawn_into_field(
+        &mut commands,
+        &mut pool,
+        difficulty.0,
+        &mut rng.rng,
+        &arena,
+        &ship_query,
+        &asteroid_query,
+        &mut deferred,
+        size,
+        count,
+    );
+    state.apply(world);
+    Ok(format!("spawned {} {:?}", count, size))
+}
+
+/// Take every asteroid off the field, without it counting as cleared
+fn clear(world: &mut World, _args: &[&str]) -> Result<String, CommandError> {
+    console::playing(world)?;
+    let count = release_asteroids(world);
+    Ok(format!("cleared {} asteroids", count))
+}
+
+/// Start the wave of another level, in place of the asteroids on the field
+fn jump_to_level(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+    console::playing(world)?;
+    let level: u32 = console::arg(args, 0)?;
+    if level == 0 {
+        return Err(CommandError::Usage);
+    }
+    release_asteroids(world);
+    world.resource_mut::<Level>().0 = level;
+    // the next wave comes in the way it does after a cleared level
+    world
+        .resource_mut::<Events<LevelCleared>>()
+        .send(LevelCleared);
+    Ok(format!("level {}", level))
+}
+
+/// put every asteroid back in the pool, returning how many there were
+fn release_asteroids(world: &mut World) -> usize {
+    let mut state: SystemState<(Commands, Query<Entity, With<Asteroid>>)> = SystemState::new(world);
+    let (mut commands, query) = state.get_mut(world);
+    for entity in query.iter() {
+        pool::release(&mut commands, entity);
+    }
+    let count = query.iter().count();
+    state.apply(world);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    const HALF_EXTENTS: Vec2 = Vec2::new(640.0, 360.0);
+
+    /// Place asteroids of `size` until `count` have been tried, giving the ones placed
+    /// and those deferred
+    fn fill(
+        area: &mut SpawnArea,
+        size: AsteroidSize,
+        count: usize,
+    ) -> (Vec<Vec2>, Vec<AsteroidSize>) {
+        let mut rng = StdRng::seed_from_u64(29);
+        let mut deferred = Vec::new();
+        let placed = (0..count)
+            .filter_map(|_| area.place(&mut rng, size, &mut deferred))
+            .collect();
+        (placed, deferred)
+    }
+
+    #[test]
+    fn asteroids_keep_clear_of_the_ships() {
+        let ships = [Vec2::new(-100.0, 0.0), Vec2::new(600.0, 300.0)];
+        let mut area = SpawnArea::new(HALF_EXTENTS, ships.into_iter(), std::iter::empty());
+        let (placed, _) = fill(&mut area, AsteroidSize::Medium, 40);
+        assert!(!placed.is_empty());
+        for position in placed {
+            for ship in ships {
+                let distance = wrapped_distance(position, ship, HALF_EXTENTS * 2.0);
+                assert!(distance - radius(AsteroidSize::Medium) >= SHIP_CLEARANCE);
             }
         }
     }
+
+    #[test]
+    fn asteroids_never_overlap() {
+        let existing = [(AsteroidSize::Large, Vec2::new(0.0, 380.0))];
+        let mut area = SpawnArea::new(HALF_EXTENTS, std::iter::empty(), existing.into_iter());
+        let (placed, _) = fill(&mut area, AsteroidSize::Large, 40);
+        let all: Vec<Vec2> = existing
+            .iter()
+            .map(|(_, position)| *position)
+            .chain(placed)
+            .collect();
+        let size = HALF_EXTENTS * 2.0;
+        for (i, a) in all.iter().enumerate() {
+            for b in all[i + 1..].iter() {
+                assert!(wrapped_distance(*a, *b, size) >= radius(AsteroidSize::Large) * 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn a_full_area_defers_the_rest() {
+        let mut area = SpawnArea::new(HALF_EXTENTS, std::iter::empty(), std::iter::empty());
+        let (placed, deferred) = fill(&mut area, AsteroidSize::Large, 100);
+        assert!(!placed.is_empty() && !deferred.is_empty());
+        assert_eq!(placed.len() + deferred.len(), 100);
+        assert!(deferred.iter().all(|size| *size == AsteroidSize::Large));
+
+        // and once full, everything waits
+        let (more, deferred) = fill(&mut area, AsteroidSize::Large, 10);
+        assert!(more.is_empty());
+        assert_eq!(deferred.len(), 10);
+    }
 }
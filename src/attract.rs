@@ -0,0 +1,174 @@
+use crate::asteroid::{spawn_fresh_wave, DeferredSpawns};
+use crate::components::*;
+use crate::difficulty::GameDifficulty;
+use crate::menu::{high_score_lines, spawn_menu, spawn_title_page, MenuInput};
+use crate::pool::Pool;
+use crate::resources::{Arena, GameMode, HighScores};
+use crate::settings::Settings;
+use crate::ship::create_ship;
+use bevy::prelude::*;
+
+/// how long each page of the title screen stays up
+const PAGE_TIME: f32 = 8.0;
+/// how long a menu can sit untouched before the demo starts again
+const IDLE_TIME: f32 = 30.0;
+/// asteroids in each wave of the demo
+const DEMO_WAVE: u32 = 4;
+
+/// Arcade style attract mode: behind the title the autopilot plays a demo game (that
+/// scores nothing), while the text switches between the title and the high scores.
+/// Any key hands over to the player by going on to the main menu, and leaving the
+/// main menu or the game over screen alone brings the demo back
+pub struct AttractPlugin;
+
+impl Plugin for AttractPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AttractTimers::default())
+            .add_system_set(SystemSet::on_enter(GameState::Title).with_system(reset_pages))
+            .add_system_set(
+                SystemSet::on_update(GameState::Title)
+                    .with_system(engage_demo_pilot)
+                    .with_system(respawn_demo_ship)
+                    .with_system(refill_demo_field)
+                    .with_system(cycle_pages),
+            );
+
+        for state in [GameState::MainMenu, GameState::GameOver] {
+            app.add_system_set(SystemSet::on_enter(state).with_system(reset_idle))
+                .add_system_set(SystemSet::on_update(state).with_system(return_when_idle));
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AttractTimers {
+    /// until the title screen shows its other page
+    page: Timer,
+    showing_scores: bool,
+    /// the high score table shown next, as an index into `GameMode::SCORE_TABLES`
+    table: usize,
+    /// until an untouched menu goes back to the demo
+    idle: Timer,
+    /// until the demo ship comes back after being destroyed
+    respawn: Timer,
+}
+
+impl Default for AttractTimers {
+    fn default() -> Self {
+        Self {
+            page: Timer::from_seconds(PAGE_TIME, TimerMode::Repeating),
+            showing_scores: false,
+            table: 0,
+            idle: Timer::from_seconds(IDLE_TIME, TimerMode::Once),
+            respawn: Timer::from_seconds(2.0, TimerMode::Once),
+        }
+    }
+}
+
+fn reset_pages(mut timers: ResMut<AttractTimers>) {
+    timers.page.reset();
+    timers.showing_scores = false;
+}
+
+fn reset_idle(mut timers: ResMut<AttractTimers>) {
+    timers.idle.reset();
+}
+
+/// the title screen's ship is flown by the computer
+fn engage_demo_pilot(
+    mut commands: Commands,
+    ships: Query<Entity, (Added<Ship>, Without<Autopilot>)>,
+) {
+    for ship in ships.iter() {
+        commands.entity(ship).insert(Autopilot);
+    }
+}
+
+/// the demo has unlimited lives
+fn respawn_demo_ship(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    difficulty: Res<GameDifficulty>,
+    mut timers: ResMut<AttractTimers>,
+    ships: Query<(), With<Ship>>,
+) {
+    if !ships.is_empty() {
+        timers.respawn.reset();
+        return;
+    }
+    if timers.respawn.tick(time.delta()).just_finished() {
+        create_ship(
+            &mut commands,
+            &settings,
+            difficulty.0,
+            Player(0),
+            Vec2::ZERO,
+        );
+    }
+}
+
+/// start another wave whenever the demo has cleared the field
+fn refill_demo_field(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    arena: Res<Arena>,
+    mut deferred: ResMut<DeferredSpawns>,
+    ships: Query<&Transform, With<Ship>>,
+    asteroids: Query<(), With<Asteroid>>,
+) {
+    if asteroids.is_empty() && deferred.0.is_empty() {
+        spawn_fresh_wave(
+            &mut commands,
+            &mut pool,
+            difficulty.0,
+            &mut rand::thread_rng(),
+            &arena,
+            &ships,
+            &mut deferred,
+            DEMO_WAVE,
+        );
+    }
+}
+
+/// take turns showing the title and the high score tables, one mode's after another
+fn cycle_pages(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    high_scores: Res<HighScores>,
+    mut timers: ResMut<AttractTimers>,
+    menus: Query<Entity, With<Menu>>,
+) {
+    if !timers.page.tick(time.delta()).just_finished() {
+        return;
+    }
+    for entity in menus.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    timers.showing_scores = !timers.showing_scores;
+    if timers.showing_scores {
+        let table = GameMode::SCORE_TABLES[timers.table];
+        timers.table = (timers.table + 1) % GameMode::SCORE_TABLES.len();
+        let mut lines = vec![table.label().to_string()];
+        lines.extend(high_score_lines(&high_scores, table));
+        lines.push("Press any key".to_string());
+        spawn_menu(&mut commands, &asset_server, "HIGH SCORES", &lines, &[]);
+    } else {
+        spawn_title_page(&mut commands, &asset_server);
+    }
+}
+
+fn return_when_idle(
+    time: Res<Time>,
+    input: MenuInput,
+    mut timers: ResMut<AttractTimers>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if input.any() {
+        timers.idle.reset();
+    } else if timers.idle.tick(time.delta()).just_finished() {
+        state.set(GameState::Title).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use crate::asteroid::radius;
+use crate::components::*;
+use crate::resources::Arena;
+use crate::settings::launch_flag;
+use crate::ship::{ShipControl, LASER_SPEED, TURN_SPEED};
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+/// only things that could hit within this many seconds are a threat
+const HORIZON: f32 = 3.0;
+/// a threat closer than this many seconds is dodged instead of shot
+const DODGE_TIME: f32 = 0.8;
+/// about the size of the ship's triangle
+const SHIP_RADIUS: f32 = 22.5;
+/// extra room kept around the ship when judging whether something will hit it
+const MARGIN: f32 = 15.0;
+/// the bot doesn't thrust any faster than this
+const CRUISE_SPEED: f32 = 250.0;
+/// lasers leave the arena after about this long, so targets further out are skipped
+const LASER_RANGE: f32 = 0.9;
+
+/// A computer player. It looks at the same things a player sees on screen and
+/// presses the same (virtual) keys through the ship's `ShipInput`: it shoots at
+/// whatever is going to hit it first, leading the target for the laser's travel
+/// time, and turns and thrusts out of the way of things it can't shoot in time.
+/// Start the game with `--autopilot` to have it fly the player's ship
+pub struct AutopilotPlugin;
+
+impl Plugin for AutopilotPlugin {
+    fn build(&self, app: &mut App) {
+        for state in [GameState::Title, GameState::Playing] {
+            app.add_system_set(
+                SystemSet::on_update(state).with_system(fly_autopilot.label(ShipControl)),
+            );
+        }
+        if launch_flag("autopilot") {
+            app.add_system(engage_autopilot);
+        }
+    }
+}
+
+fn engage_autopilot(mut commands: Commands, ships: Query<Entity, Added<Ship>>) {
+    for ship in ships.iter() {
+        commands.entity(ship).insert(Autopilot);
+    }
+}
+
+/// something the ship could run into, as seen from the ship
+struct Hazard {
+    /// where it is, relative to the ship (and the nearest way round the arena)
+    offset: Vec2,
+    velocity: Vec2,
+    /// relative to the ship
+    closing_velocity: Vec2,
+    radius: f32,
+    shootable: bool,
+}
+
+impl Hazard {
+    /// Seconds until it touches the ship if neither changes course, or `None` when it
+    /// passes by
+    fn time_to_impact(&self) -> Option<f32> {
+        let reach = self.radius + SHIP_RADIUS + MARGIN;
+        if self.offset.length() < reach {
+            return Some(0.0);
+        }
+        // |offset + closing_velocity * t| = reach
+        let a = self.closing_velocity.length_squared();
+        let b = 2.0 * self.offset.dot(self.closing_velocity);
+        let c = self.offset.length_squared() - reach * reach;
+        let discriminant = b * b - 4.0 * a * c;
+        if a == 0.0 || discriminant < 0.0 {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / (2.0 * a);
+        (t >= 0.0).then_some(t)
+    }
+
+    /// How long a laser fired now takes to reach it, or `None` if it can't catch up
+    fn intercept_time(&self) -> Option<f32> {
+        // |offset + velocity * t| = LASER_SPEED * t
+        let a = self.velocity.length_squared() - LASER_SPEED * LASER_SPEED;
+        let b = 2.0 * self.offset.dot(self.velocity);
+        let c = self.offset.length_squared();
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        // a is negative (nothing outruns a laser), so this is the positive root
+        let t = (-b - discriminant.sqrt()) / (2.0 * a);
+        (t > 0.0).then_some(t)
+    }
+}
+
+/// The shortest way from `from` to `to` on a playfield that wraps around
+fn wrapped_offset(from: Vec2, to: Vec2, arena: &Arena) -> Vec2 {
+    let size = Vec2::new(arena.width, arena.height);
+    let delta = to - from;
+    delta - (delta / size).round() * size
+}
+
+/// the angle to turn by to face `direction`, between -PI and PI
+fn turn_towards(transform: &Transform, direction: Vec2) -> f32 {
+    let facing = transform.up();
+    let heading = facing.y.atan2(facing.x);
+    let wanted = direction.y.atan2(direction.x);
+    (wanted - heading + PI).rem_euclid(TAU) - PI
+}
+
+#[allow(clippy::type_complexity)]
+fn fly_autopilot(
+    arena: Res<Arena>,
+    mut ships: Query<(&mut ShipInput, &Transform, &Velocity), (With<Ship>, With<Autopilot>)>,
+    hazards: Query<
+        (&Transform, &Velocity, Option<&Asteroid>, Option<&Ufo>),
+        Or<(With<Asteroid>, With<Ufo>, With<EnemyLaser>)>,
+    >,
+) {
+    for (mut input, transform, ship_velocity) in ships.iter_mut() {
+        let position = transform.translation.truncate();
+        let hazards: Vec<Hazard> = hazards
+            .iter()
+            .map(|(hazard_transform, velocity, asteroid, ufo)| Hazard {
+                offset: wrapped_offset(position, hazard_transform.translation.truncate(), &arena),
+                velocity: velocity.linvel,
+                closing_velocity: velocity.linvel - ship_velocity.linvel,
+                radius: match (asteroid, ufo) {
+                    (Some(asteroid), _) => radius(asteroid.0),
+                    (None, Some(_)) => 20.0,
+                    (None, None) => 2.0,
+                },
+                shootable: asteroid.is_some() || ufo.is_some(),
+            })
+            .collect();
+
+        let threats: Vec<(&Hazard, f32)> = hazards
+            .iter()
+            .filter_map(|hazard| Some((hazard, hazard.time_to_impact()?)))
+            .filter(|(_, time)| *time < HORIZON)
+            .collect();
+        let first_threat = threats
+            .iter()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .copied();
+
+        // the most urgent threat that can be shot first, else whatever is quickest to hit
+        let target = threats
+            .iter()
+            .filter(|(hazard, _)| hazard.shootable)
+            .filter_map(|(hazard, impact)| {
+                let intercept = hazard.intercept_time()?;
+                (intercept < *impact).then_some((*hazard, *impact))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(hazard, _)| hazard)
+            .or_else(|| {
+                hazards
+                    .iter()
+                    .filter(|hazard| hazard.shootable)
+                    .filter_map(|hazard| Some((hazard, hazard.intercept_time()?)))
+                    .filter(|(_, time)| *time < LASER_RANGE)
+                    .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(hazard, _)| hazard)
+            });
+
+        *input = ShipInput::default();
+        let speed = ship_velocity.linvel.length();
+
+        match first_threat {
+            // too close to shoot its way out: get out of the way, sideways to its
+            // approach and away from where it would hit
+            Some((threat, impact)) if impact < DODGE_TIME && !is_target(target, threat) => {
+                let miss = threat.offset + threat.closing_velocity * impact;
+                let sideways = threat.closing_velocity.perp().normalize_or_zero();
+                let escape = if sideways.dot(miss) > 0.0 {
+                    -sideways
+                } else {
+                    sideways
+                };
+                let turn = turn_towards(transform, escape);
+                steer(&mut input, turn);
+                input.thrust = turn.abs() < 0.6 && speed < CRUISE_SPEED * 1.5;
+            }
+            _ => {
+                if let Some(target) = target {
+                    let time = target.intercept_time().unwrap_or_default();
+                    let aim = target.offset + target.velocity * time;
+                    let turn = turn_towards(transform, aim);
+                    steer(&mut input, turn);
+                    // close enough when the laser would pass within the target's edge
+                    let tolerance = (target.radius / aim.length().max(1.0)).atan().max(0.03);
+                    input.fire = turn.abs() < tolerance;
+                }
+                // drift towards the middle when there is nothing to do, without
+                // picking up much speed
+                if threats.is_empty() && position.length() > arena.height / 3.0 {
+                    let home = turn_towards(transform, -position);
+                    input.thrust = home.abs() < 0.3 && speed < CRUISE_SPEED / 2.0;
+                }
+            }
+        }
+    }
+}
+
+fn is_target(target: Option<&Hazard>, hazard: &Hazard) -> bool {
+    target.is_some_and(|target| std::ptr::eq(target, hazard))
+}
+
+/// press left or right to turn by `turn`, leaving small errors alone so the ship
+/// doesn't wobble around its aim
+fn steer(input: &mut ShipInput, turn: f32) {
+    if turn.abs() < TURN_SPEED / 2.0 {
+        return;
+    }
+    input.left = turn > 0.0;
+    input.right = turn < 0.0;
+}
@@ -0,0 +1,161 @@
+use crate::components::*;
+use crate::events::{AsteroidDestroyed, ShipDestroyed, UfoDestroyed};
+use crate::resources::{Arena, CameraEffects};
+use crate::settings::Settings;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy_rapier2d::prelude::*;
+
+/// furthest the camera moves and turns at full trauma
+const MAX_SHAKE_OFFSET: f32 = 14.0;
+const MAX_SHAKE_ANGLE: f32 = 0.03;
+/// trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+/// how quickly the zoom springs back, per second
+const ZOOM_RECOVERY: f32 = 12.0;
+
+/// Plays out the camera effects requested when things are destroyed while playing
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraEffects>()
+            .add_system(apply_camera_effects)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(trigger_effects)
+                    .with_system(hit_stop.after(trigger_effects)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(reset_effects));
+    }
+}
+
+/// Where the mouse cursor is in the arena, if it is over it. The arena fills the
+/// letterboxed viewport of whichever camera draws to the window (the game camera,
+/// or the retro display's). Shake and zoom are small enough to leave out
+pub fn cursor_in_arena(windows: &Windows, arena: &Arena, cameras: &Query<&Camera>) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()? * window.scale_factor() as f32;
+    let viewport = cameras
+        .iter()
+        .filter(|camera| matches!(camera.target, RenderTarget::Window(_)))
+        .find_map(|camera| camera.viewport.as_ref())?;
+    // the cursor is measured from the bottom of the window, the viewport from the top
+    let from_top = Vec2::new(cursor.x, window.physical_height() as f32 - cursor.y);
+    let relative =
+        (from_top - viewport.physical_position.as_vec2()) / viewport.physical_size.as_vec2();
+    if relative.cmplt(Vec2::ZERO).any() || relative.cmpgt(Vec2::ONE).any() {
+        return None;
+    }
+    Some(Vec2::new(
+        (relative.x - 0.5) * arena.width,
+        (0.5 - relative.y) * arena.height,
+    ))
+}
+
+/// the bigger the bang, the harder the camera reacts
+fn trigger_effects(
+    settings: Res<Settings>,
+    mut effects: ResMut<CameraEffects>,
+    mut asteroid_events: EventReader<AsteroidDestroyed>,
+    mut ufo_events: EventReader<UfoDestroyed>,
+    mut ship_events: EventReader<ShipDestroyed>,
+) {
+    // read them all either way, so they don't pile up while effects are off
+    let asteroids: Vec<AsteroidSize> = asteroid_events.iter().map(|event| event.size).collect();
+    let ufos = ufo_events.iter().count();
+    let ships = ship_events.iter().count();
+    if settings.accessibility.reduce_motion {
+        return;
+    }
+
+    for size in asteroids {
+        if size == AsteroidSize::Large {
+            effects.shake(0.3);
+            effects.punch(0.015);
+            effects.freeze(0.04);
+        } else {
+            effects.freeze(0.025);
+        }
+    }
+    for _ in 0..ufos {
+        effects.shake(0.3);
+        effects.punch(0.02);
+        effects.freeze(0.05);
+    }
+    for _ in 0..ships {
+        effects.shake(0.7);
+        effects.punch(0.04);
+        effects.freeze(0.12);
+    }
+}
+
+/// stop the physics for a moment so the hit lands
+fn hit_stop(
+    time: Res<Time>,
+    mut effects: ResMut<CameraEffects>,
+    mut config: ResMut<RapierConfiguration>,
+    mut frozen: Local<bool>,
+) {
+    if effects.hit_stop > 0.0 {
+        effects.hit_stop = (effects.hit_stop - time.delta_seconds()).max(0.0);
+        config.physics_pipeline_active = false;
+        *frozen = true;
+    } else if *frozen {
+        config.physics_pipeline_active = true;
+        *frozen = false;
+    }
+}
+
+/// the game can end in the middle of a freeze; don't leave the physics stopped
+fn reset_effects(mut effects: ResMut<CameraEffects>, mut config: ResMut<RapierConfiguration>) {
+    if effects.hit_stop > 0.0 {
+        config.physics_pipeline_active = true;
+    }
+    effects.hit_stop = 0.0;
+}
+
+/// Shake and zoom the game camera by whatever trauma and zoom are left, and wear
+/// them off
+fn apply_camera_effects(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut effects: ResMut<CameraEffects>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<GameCamera>>,
+) {
+    let delta = time.delta_seconds();
+    if settings.accessibility.reduce_motion {
+        effects.trauma = 0.0;
+        effects.zoom = 0.0;
+    }
+
+    // squared so small bumps stay subtle and big ones really rattle
+    let shake = effects.trauma * effects.trauma;
+    // overlapping waves of different speeds make a jitter that doesn't look regular
+    let t = time.elapsed_seconds();
+    let wobble = |speed: f32, phase: f32| (t * speed + phase).sin() * (t * speed * 1.7).cos();
+    let offset = Vec2::new(wobble(47.0, 0.0), wobble(53.0, 1.3)) * MAX_SHAKE_OFFSET * shake;
+    let angle = wobble(41.0, 2.6) * MAX_SHAKE_ANGLE * shake;
+
+    for (mut transform, mut projection) in cameras.iter_mut() {
+        let rotation = Quat::from_rotation_z(angle);
+        if transform.translation.truncate() != offset || transform.rotation != rotation {
+            transform.translation = offset.extend(transform.translation.z);
+            transform.rotation = rotation;
+        }
+        let scale = 1.0 - effects.zoom;
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+
+    if effects.trauma > 0.0 {
+        effects.trauma = (effects.trauma - TRAUMA_DECAY * delta).max(0.0);
+    }
+    if effects.zoom > 0.0 {
+        effects.zoom *= (-ZOOM_RECOVERY * delta).exp();
+        if effects.zoom < 0.0005 {
+            effects.zoom = 0.0;
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::components::*;
+use crate::console::{AddConsoleCommand, CommandError};
+use crate::resources::{GameMode, GodMode};
+use crate::wrap::owner_of;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier2d::parry::query::intersection_test;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::{Isometry, Vector};
+
+/// Turns rapier's collision events into typed hits, looking up each entity once
+/// instead of every handler scanning every entity for every event
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AsteroidHit>()
+            .add_event::<UfoHit>()
+            .add_event::<ShipHit>()
+            .add_console_command("god", "", god);
+        for state in [GameState::Title, GameState::Playing] {
+            app.add_system_set(
+                SystemSet::on_update(state)
+                    .with_system(dispatch_collisions.label(DispatchCollisions)),
+            );
+        }
+    }
+}
+
+/// handlers run after this, so hits are dealt with in the frame they happen
+#[derive(SystemLabel)]
+pub struct DispatchCollisions;
+
+/// a laser hit an asteroid
+pub struct AsteroidHit {
+    pub asteroid: Entity,
+    pub laser: Entity,
+    pub by: Player,
+}
+
+/// one of the players' lasers hit the UFO
+pub struct UfoHit {
+    pub ufo: Entity,
+    pub laser: Entity,
+    pub by: Player,
+}
+
+/// the ship touched an asteroid, the UFO or one of its lasers, or (in versus) got
+/// shot by the other player
+pub struct ShipHit {
+    pub ship: Entity,
+    /// the other player's laser, which is spent
+    pub laser: Option<Entity>,
+    pub by: Option<Player>,
+}
+
+/// what a collider is, as far as collisions go
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    Ship,
+    Asteroid,
+    Laser,
+    EnemyLaser,
+    Ufo,
+}
+
+/// One side of a contact: what it is, and whose ship or laser it is
+pub type Side = (Body, Option<Player>);
+
+/// What comes of two things touching
+#[derive(Debug, PartialEq, Eq)]
+pub enum Contact {
+    /// The ship is destroyed, by the other player if they shot it. Their laser is
+    /// spent; anything else that hits the ship stays around
+    Ship { by: Option<Player> },
+    /// the asteroid is destroyed by the laser, which is spent
+    Asteroid { by: Player },
+    /// the UFO is shot down by the laser, which is spent
+    Ufo { by: Player },
+}
+
+impl Contact {
+    /// whether the other side of the contact is used up as well as the one hit
+    pub fn spends_both(&self) -> bool {
+        !matches!(self, Contact::Ship { by: None })
+    }
+}
+
+/// Put the two sides of a contact in `Body` order, so only half the combinations
+/// need matching. `T` is whatever identifies them
+pub fn ordered<T>(a: (T, Side), b: (T, Side)) -> ((T, Side), (T, Side)) {
+    if (a.1).0 as u8 <= (b.1).0 as u8 {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// The rules for `a` and `b` touching, once they are `ordered`: the ship, asteroid or
+/// UFO hit is whichever of them it says
+pub fn contact(mode: &GameMode, god_mode: bool, a: Side, b: Side) -> Option<Contact> {
+    match (a, b) {
+        // nothing can hurt the ship in the sandbox, or in god mode
+        ((Body::Ship, _), (Body::Asteroid | Body::EnemyLaser | Body::Ufo, _))
+            if *mode != GameMode::Sandbox && !god_mode =>
+        {
+            Some(Contact::Ship { by: None })
+        }
+        // lasers only hurt ships in versus, and never the one that fired them
+        ((Body::Ship, ship), (Body::Laser, Some(by)))
+            if *mode == GameMode::Versus && !god_mode && ship != Some(by) =>
+        {
+            Some(Contact::Ship { by: Some(by) })
+        }
+        ((Body::Asteroid, _), (Body::Laser, Some(by))) => Some(Contact::Asteroid { by }),
+        ((Body::Laser, Some(by)), (Body::Ufo, _)) => Some(Contact::Ufo { by }),
+        _ => None,
+    }
+}
+
+/// Whether two colliders overlap, each at a position and turned by an angle. The
+/// same test rapier makes, for when there is no physics world to ask
+pub fn touching(a: &Collider, a_at: (Vec2, f32), b: &Collider, b_at: (Vec2, f32)) -> bool {
+    let isometry =
+        |(position, angle): (Vec2, f32)| Isometry::new(Vector::new(position.x, position.y), angle);
+    intersection_test(&isometry(a_at), &*a.raw, &isometry(b_at), &*b.raw).unwrap_or(false)
+}
+
+type BodyQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static Ship>,
+        Option<&'static Asteroid>,
+        Option<&'static Laser>,
+        Option<&'static EnemyLaser>,
+        Option<&'static Ufo>,
+    ),
+>;
+
+/// what `entity` is, and whose ship it is or who fired it
+fn classify(entity: Entity, bodies: &BodyQuery, players: &Query<&Player>) -> Option<Side> {
+    match bodies.get(entity).ok()? {
+        (Some(_), ..) => Some((Body::Ship, players.get(entity).ok().copied())),
+        (_, Some(_), ..) => Some((Body::Asteroid, None)),
+        (_, _, Some(laser), ..) => Some((Body::Laser, Some(laser.owner))),
+        (_, _, _, Some(_), _) => Some((Body::EnemyLaser, None)),
+        (.., Some(_)) => Some((Body::Ufo, None)),
+        _ => None,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn dispatch_collisions(
+    mode: Res<GameMode>,
+    god_mode: Res<GodMode>,
+    mut collision_events: EventReader<CollisionEvent>,
+    ghosts: Query<&Ghost>,
+    bodies: BodyQuery,
+    players: Query<&Player>,
+    mut asteroid_hits: EventWriter<AsteroidHit>,
+    mut ufo_hits: EventWriter<UfoHit>,
+    mut ship_hits: EventWriter<ShipHit>,
+) {
+    // a laser is spent on the first thing it hits, and something can only be destroyed
+    // once. Several contacts in one frame (or through a ghost) would do it again
+    let mut used = HashSet::new();
+    for event in collision_events.iter() {
+        let CollisionEvent::Started(h1, h2, _flags) = event else {
+            continue;
+        };
+        // a hit on a ghost is a hit on the entity it mirrors
+        let (e1, e2) = (owner_of(*h1, &ghosts), owner_of(*h2, &ghosts));
+        if used.contains(&e1) || used.contains(&e2) {
+            continue;
+        }
+        let (Some(s1), Some(s2)) = (
+            classify(e1, &bodies, &players),
+            classify(e2, &bodies, &players),
+        ) else {
+            continue;
+        };
+        let ((a, side_a), (b, side_b)) = ordered((e1, s1), (e2, s2));
+        let Some(contact) = contact(&mode, god_mode.0, side_a, side_b) else {
+            continue;
+        };
+        used.insert(a);
+        if contact.spends_both() {
+            used.insert(b);
+        }
+        match contact {
+            Contact::Ship { by } => ship_hits.send(ShipHit {
+                ship: a,
+                laser: by.map(|_| b),
+                by,
+            }),
+            Contact::Asteroid { by } => asteroid_hits.send(AsteroidHit {
+                asteroid: a,
+                laser: b,
+                by,
+            }),
+            Contact::Ufo { by } => ufo_hits.send(UfoHit {
+                ufo: b,
+                laser: a,
+                by,
+            }),
+        }
+    }
+}
+
+/// toggle god mode
+fn god(world: &mut World, _args: &[&str]) -> Result<String, CommandError> {
+    let mut god_mode = world.resource_mut::<GodMode>();
+    god_mode.0 = !god_mode.0;
+    Ok(format!(
+        "god mode {}",
+        if god_mode.0 { "on" } else { "off" }
+    ))
+}
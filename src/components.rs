@@ -1,40 +1,235 @@
-use bevy::prelude::Component;
+use crate::resources::GameMode;
+use crate::settings::SettingField;
+use bevy::prelude::{Color, Component};
+use bevy::time::Stopwatch;
+use serde::{Deserialize, Serialize};
 
 /// enum for game states
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum GameState {
+    Title,
+    MainMenu,
+    ModeSelect,
+    Settings,
     Playing,
+    /// an online game, run by the rollback session instead of the usual systems
+    Online,
+    Paused,
     GameOver,
+    HighScores,
 }
 
 /// enum for asteroid sizes
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum AsteroidSize {
     Large,
     Medium,
     Small,
-    Dead,
 }
 
+/// what happens when a menu item is activated
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MenuAction {
+    Goto(GameState),
+    /// pick up the saved game
+    Continue,
+    /// start a new game of the given mode
+    Start(GameMode),
+    Resume,
+    /// change the value of a setting with left/right
+    Adjust(SettingField),
+    Quit,
+}
+
+/// root of any menu or screen overlay. Despawned when its state is exited
+#[derive(Component)]
+pub struct Menu;
+
+/// a selectable line in a menu
+#[derive(Component)]
+pub struct MenuItem {
+    pub index: usize,
+    pub action: MenuAction,
+}
+
+/// the camera the game is drawn with
 #[derive(Component)]
-pub struct Instructions;
+pub struct GameCamera;
+
+/// the camera and the screen the retro display mode shows the game on
+#[derive(Component)]
+pub struct CrtScreen;
+
+/// the rectangle behind the playfield, inside the letterbox bars
+#[derive(Component)]
+pub struct ArenaBackground;
+
+/// text showing the score/level while playing
+#[derive(Component)]
+pub struct Hud;
+
+/// the line from where a sandbox drag started to the cursor
+#[derive(Component)]
+pub struct DragLine;
+
+/// text showing the sandbox's tools and what they are set to
+#[derive(Component)]
+pub struct SandboxHud;
+
+/// the developer console's drop-down panel
+#[derive(Component)]
+pub struct ConsoleWindow;
+
+/// the console's output and input line
+#[derive(Component)]
+pub struct ConsoleText;
+
+/// the debug overlay's text
+#[derive(Component)]
+pub struct DebugText;
+
+/// a line the debug overlay draws along a body's velocity
+#[derive(Component)]
+pub struct VelocityArrow;
 
 #[derive(Component)]
 pub struct Ship;
 
+/// Which of the people playing a ship belongs to, counting from 0. Their score and
+/// lives are kept under the same index in `Players`
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Player(pub usize);
+
+impl Player {
+    /// what the ship and the player's score are drawn in, to tell them apart
+    pub fn color(&self) -> Color {
+        match self.0 {
+            0 => Color::WHITE,
+            _ => Color::rgb(0.35, 0.85, 1.0),
+        }
+    }
+}
+
+/// time since the ship last fired. Another laser can't be fired until the
+/// difficulty's cooldown has passed
+#[derive(Component, Default, Clone)]
+pub struct FireTimer(pub Stopwatch);
+
+impl FireTimer {
+    /// a timer that has already waited out `cooldown`, so a new ship can fire at once
+    pub fn ready(cooldown: f32) -> Self {
+        let mut stopwatch = Stopwatch::new();
+        stopwatch.set_elapsed(bevy::utils::Duration::from_secs_f32(cooldown));
+        Self(stopwatch)
+    }
+
+    /// Count `delta` towards the cooldown. True, starting it over, when the ship
+    /// wants to `fire` and `cooldown` has passed since its last shot
+    pub fn fire(&mut self, delta: bevy::utils::Duration, cooldown: f32, fire: bool) -> bool {
+        self.0.tick(delta);
+        if !fire || self.0.elapsed().as_secs_f32() < cooldown {
+            return false;
+        }
+        self.0.reset();
+        true
+    }
+}
+
+/// What the ship is being told to do this frame, by the keyboard or the autopilot
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShipInput {
+    pub left: bool,
+    pub right: bool,
+    pub thrust: bool,
+    pub fire: bool,
+}
+
+impl ShipInput {
+    /// packed into a byte, for sending over the network
+    pub fn to_bits(self) -> u8 {
+        self.left as u8
+            | (self.right as u8) << 1
+            | (self.thrust as u8) << 2
+            | (self.fire as u8) << 3
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            left: bits & 1 != 0,
+            right: bits & 1 << 1 != 0,
+            thrust: bits & 1 << 2 != 0,
+            fire: bits & 1 << 3 != 0,
+        }
+    }
+}
+
+/// a ship flown by the computer instead of the keyboard
 #[derive(Component)]
-pub struct Laser;
+pub struct Autopilot;
+
+#[derive(Component)]
+pub struct Laser {
+    /// who fired it, and gets the points for what it hits
+    pub owner: Player,
+}
 
 #[derive(Component)]
 pub struct Asteroid(pub AsteroidSize);
 
+/// the flying saucer that shows up now and then to shoot at the ship
+#[derive(Component, Clone)]
+pub struct Ufo {
+    /// time until the next shot
+    pub fire_timer: bevy::time::Timer,
+    /// time until the next change of vertical direction
+    pub turn_timer: bevy::time::Timer,
+}
+
+/// a shot fired by a UFO. Only the ship cares about these
+#[derive(Component)]
+pub struct EnemyLaser;
+
+/// a piece of debris from an explosion. Hidden particles are waiting in the pool
+#[derive(Component)]
+pub struct Particle {
+    pub velocity: bevy::prelude::Vec2,
+    /// radians per second
+    pub spin: f32,
+    /// time until it disappears
+    pub life: bevy::time::Timer,
+}
+
+/// the flame behind the ship, shown while it thrusts
+#[derive(Component)]
+pub struct Exhaust;
+
 /// any entity that should "wrap" when hitting the edge of the screen
 #[derive(Component)]
 pub struct Wrapper;
 
+/// a copy of a wrapper on the opposite side of the arena, drawn and collided with
+/// while the wrapper straddles an edge
+#[derive(Component)]
+pub struct Ghost {
+    pub owner: bevy::prelude::Entity,
+    /// which of the owner's coordinates are mirrored
+    pub axes: bevy::prelude::BVec2,
+}
+
 /// describes an entity with a width and height
 #[derive(Component, Clone, Copy, PartialEq)]
 pub struct Dimensions {
     pub width: f32,
     pub height: f32,
 }
+
+/// what a recycled entity is built as; it keeps its mesh between uses
+#[derive(Component, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Pooled {
+    Laser,
+    Asteroid(AsteroidSize),
+}
+
+/// a pooled entity that is hidden and out of the physics world, waiting to be used
+#[derive(Component)]
+pub struct Parked;
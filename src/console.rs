@@ -0,0 +1,374 @@
+use crate::components::*;
+use crate::resources::Cheated;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+
+/// lines of output kept on screen
+const LOG_LINES: usize = 12;
+/// lines kept for Up and Down to go back through
+const HISTORY_LINES: usize = 50;
+
+/// A drop-down developer console, opened with the backtick key. Each plugin adds its
+/// own commands with `App::add_console_command`; `help` lists them. While the console
+/// is open it has the keyboard to itself. Any command but `help` that goes through
+/// keeps the game out of the high scores
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Console::default())
+            .add_console_command("help", "", help)
+            .add_startup_system(spawn_console)
+            // before anything else sees the keys
+            .add_system_to_stage(CoreStage::PreUpdate, console_input.after(InputSystem))
+            // once the lines typed this frame are in, and before the game moves on
+            .add_system_to_stage(CoreStage::PreUpdate, run_console_commands.at_end())
+            .add_system(update_console_text);
+    }
+}
+
+/// What a command does with the words typed after its name. The line it returns,
+/// if any, is shown in the console
+pub type CommandHandler = fn(&mut World, &[&str]) -> Result<String, CommandError>;
+
+pub enum CommandError {
+    /// the arguments were wrong; the command's usage is shown
+    Usage,
+    /// the command could not be done, and why
+    Failed(String),
+}
+
+struct ConsoleCommand {
+    usage: &'static str,
+    run: CommandHandler,
+}
+
+/// Every command the console knows, by name
+#[derive(Resource, Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+pub trait AddConsoleCommand {
+    /// Make `name` a console command. `usage` describes its arguments, for `help`
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        run: CommandHandler,
+    ) -> &mut Self;
+}
+
+impl AddConsoleCommand for App {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        run: CommandHandler,
+    ) -> &mut Self {
+        self.init_resource::<ConsoleCommands>();
+        self.world
+            .resource_mut::<ConsoleCommands>()
+            .0
+            .insert(name, ConsoleCommand { usage, run });
+        self
+    }
+}
+
+/// the argument at `index`, parsed
+pub fn arg<T: FromStr>(args: &[&str], index: usize) -> Result<T, CommandError> {
+    args.get(index)
+        .and_then(|arg| arg.parse().ok())
+        .ok_or(CommandError::Usage)
+}
+
+/// For commands that only make sense with a game going
+pub fn playing(world: &World) -> Result<(), CommandError> {
+    if *world.resource::<State<GameState>>().current() == GameState::Playing {
+        Ok(())
+    } else {
+        Err(CommandError::Failed("only while playing".to_string()))
+    }
+}
+
+/// The console's input line, what it has shown and the lines entered before
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    log: VecDeque<String>,
+    history: Vec<String>,
+    /// the line of the history Up and Down have got to; its length for the new line
+    browsing: usize,
+    /// lines entered, waiting to be run
+    submitted: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.log.push_back(line.to_string());
+        }
+        while self.log.len() > LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    /// Queue `line` to run, and keep it in the history unless it was the last one
+    fn submit(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() {
+            if self.history.last().map(String::as_str) != Some(line) {
+                self.history.push(line.to_string());
+                if self.history.len() > HISTORY_LINES {
+                    self.history.remove(0);
+                }
+            }
+            self.submitted.push(line.to_string());
+        }
+        self.browsing = self.history.len();
+    }
+}
+
+fn spawn_console(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    top: Val::Px(0.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+            visibility: Visibility { is_visible: false },
+            z_index: ZIndex::Global(10),
+            ..default()
+        })
+        .insert(ConsoleWindow)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(ConsoleText);
+        });
+}
+
+/// Typing, Backspace, Enter to run the line, Up and Down through the history and
+/// Escape or the backtick to close
+fn console_input(
+    mut console: ResMut<Console>,
+    mut keyboard: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+) {
+    if keyboard.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        keyboard.reset(KeyCode::Grave);
+    }
+    if !console.open {
+        characters.clear();
+        return;
+    }
+
+    for character in characters.iter() {
+        if character.char != '`' && !character.char.is_control() {
+            console.input.push(character.char);
+        }
+    }
+    if keyboard.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+    if keyboard.just_pressed(KeyCode::Up) && console.browsing > 0 {
+        console.browsing -= 1;
+        console.input = console.history[console.browsing].clone();
+    }
+    if keyboard.just_pressed(KeyCode::Down) && console.browsing < console.history.len() {
+        console.browsing += 1;
+        console.input = console
+            .history
+            .get(console.browsing)
+            .cloned()
+            .unwrap_or_default();
+    }
+    if keyboard.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        console.submit(&line);
+    }
+    if keyboard.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    }
+
+    // the game doesn't get the keys typed into the console
+    keyboard.reset_all();
+}
+
+/// Run the lines entered this frame. With the whole world to hand, so a command can
+/// reach whatever it needs
+fn run_console_commands(world: &mut World) {
+    if world.resource::<Console>().submitted.is_empty() {
+        return;
+    }
+    let lines = std::mem::take(&mut world.resource_mut::<Console>().submitted);
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        let command = world
+            .resource::<ConsoleCommands>()
+            .0
+            .get(name.as_str())
+            .map(|command| (command.usage, command.run));
+
+        let output = match command {
+            Some((usage, run)) => match run(world, args) {
+                Ok(output) => {
+                    if name != "help" {
+                        world.resource_mut::<Cheated>().0 = true;
+                    }
+                    output
+                }
+                Err(CommandError::Usage) => format!("usage: {} {}", name, usage),
+                Err(CommandError::Failed(reason)) => format!("{}: {}", name, reason),
+            },
+            None => format!("unknown command {}, try help", name),
+        };
+        let mut console = world.resource_mut::<Console>();
+        console.print(&format!("> {}", line));
+        console.print(&output);
+    }
+}
+
+/// list the commands
+fn help(world: &mut World, _args: &[&str]) -> Result<String, CommandError> {
+    Ok(world
+        .resource::<ConsoleCommands>()
+        .0
+        .iter()
+        .map(|(name, command)| format!("{} {}", name, command.usage))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn update_console_text(
+    console: Res<Console>,
+    mut windows: Query<&mut Visibility, With<ConsoleWindow>>,
+    mut texts: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut visibility in windows.iter_mut() {
+        visibility.is_visible = console.open;
+    }
+    let mut value = console
+        .log
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    value.push_str(&format!("> {}_", console.input));
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// what the `set` command changes, to show it ran
+    #[derive(Resource, Default)]
+    struct Value(u32);
+
+    fn set(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+        let value: u32 = arg(args, 0)?;
+        if value == 0 {
+            return Err(CommandError::Failed("not zero".to_string()));
+        }
+        world.resource_mut::<Value>().0 = value;
+        Ok(format!("set to {}", value))
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Console>();
+        world.init_resource::<Cheated>();
+        world.init_resource::<Value>();
+        let mut commands = ConsoleCommands::default();
+        for (name, usage, run) in [
+            ("help", "", help as CommandHandler),
+            ("set", "<number>", set),
+        ] {
+            commands.0.insert(name, ConsoleCommand { usage, run });
+        }
+        world.insert_resource(commands);
+        world
+    }
+
+    /// run `line` and give back what it printed after the echo
+    fn run(world: &mut World, line: &str) -> Vec<String> {
+        let before = world.resource::<Console>().log.len();
+        world.resource_mut::<Console>().submit(line);
+        run_console_commands(world);
+        let log = &world.resource::<Console>().log;
+        assert_eq!(log[before], format!("> {}", line.trim()));
+        log.iter().skip(before + 1).cloned().collect()
+    }
+
+    #[test]
+    fn commands_run_by_name() {
+        let mut world = world();
+        assert_eq!(run(&mut world, "  SET 7 "), ["set to 7"]);
+        assert_eq!(world.resource::<Value>().0, 7);
+        assert_eq!(
+            run(&mut world, "nothing"),
+            ["unknown command nothing, try help"]
+        );
+    }
+
+    #[test]
+    fn bad_arguments_show_the_usage_or_the_reason() {
+        let mut world = world();
+        assert_eq!(run(&mut world, "set"), ["usage: set <number>"]);
+        assert_eq!(run(&mut world, "set seven"), ["usage: set <number>"]);
+        assert_eq!(run(&mut world, "set 0"), ["set: not zero"]);
+        assert_eq!(world.resource::<Value>().0, 0);
+        // nothing went through, so the run still counts
+        assert!(!world.resource::<Cheated>().0);
+    }
+
+    #[test]
+    fn only_commands_that_go_through_count_as_cheating() {
+        let mut world = world();
+        assert_eq!(run(&mut world, "help"), ["help ", "set <number>"]);
+        assert!(!world.resource::<Cheated>().0);
+        run(&mut world, "set 3");
+        assert!(world.resource::<Cheated>().0);
+    }
+
+    #[test]
+    fn the_history_keeps_the_latest_lines() {
+        let mut console = Console::default();
+        for index in 0..HISTORY_LINES + 10 {
+            console.submit(&format!("set {}", index));
+            // the same line twice is only kept once
+            console.submit(&format!("set {}", index));
+        }
+        console.submit("   ");
+        assert_eq!(console.history.len(), HISTORY_LINES);
+        assert_eq!(console.history[0], "set 10");
+        assert_eq!(console.browsing, HISTORY_LINES);
+        assert_eq!(console.submitted.len(), (HISTORY_LINES + 10) * 2);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::components::*;
+use crate::resources::Arena;
+use crate::settings::Settings;
+use bevy::asset::load_internal_asset;
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::camera::{RenderTarget, ScalingMode};
+use bevy::render::render_resource::{
+    AsBindGroup, Extent3d, ShaderRef, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureUsages,
+};
+use bevy::render::texture::BevyDefault;
+use bevy::render::view::RenderLayers;
+use bevy::sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle};
+
+const CRT_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x7a3c_91e4_52d0_b86f);
+
+/// the render layer only the screen quad and the camera looking at it are on
+const CRT_LAYER: u8 = 1;
+
+/// Retro display mode: the game is drawn into a texture, which is then shown with
+/// glow, scanlines and a curved tube. Trails come from the arena background being
+/// drawn see-through over the previous frames instead of clearing them
+pub struct CrtPlugin;
+
+impl Plugin for CrtPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(app, CRT_SHADER_HANDLE, "crt.wgsl", Shader::from_wgsl);
+        app.add_plugin(Material2dPlugin::<CrtMaterial>::default())
+            .add_system(toggle_crt);
+    }
+}
+
+/// The knobs of the effect, packed into vectors since those are the uniforms WebGL2
+/// handles without fuss. Must match the bindings in crt.wgsl
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "3f0c2a1e-8c55-4a3b-9a0e-6d4e2b7c1f90"]
+pub struct CrtMaterial {
+    /// glow strength, glow radius in pixels of the game's image, rings of glow samples
+    /// (the expensive part) and an overall brightness making up for the darkening
+    #[uniform(0)]
+    picture: Vec4,
+    /// scanline darkness, curvature, vignette and shadow mask darkness
+    #[uniform(1)]
+    tube: Vec4,
+    /// what the game camera drew
+    #[texture(2)]
+    #[sampler(3)]
+    screen: Handle<Image>,
+}
+
+impl CrtMaterial {
+    fn new(screen: Handle<Image>) -> Self {
+        // browsers on WebGL2 tend to be on weaker hardware, so they get a cheaper glow
+        let glow_rings = if cfg!(target_arch = "wasm32") {
+            1.0
+        } else {
+            3.0
+        };
+        Self {
+            picture: Vec4::new(1.2, 4.0, glow_rings, 1.4),
+            tube: Vec4::new(0.3, 0.04, 0.25, 0.2),
+            screen,
+        }
+    }
+}
+
+impl Material2d for CrtMaterial {
+    fn fragment_shader() -> ShaderRef {
+        CRT_SHADER_HANDLE.typed().into()
+    }
+}
+
+/// Switch the game camera between drawing to the window and drawing to the CRT
+/// screen whenever the setting changes
+#[allow(clippy::too_many_arguments)]
+fn toggle_crt(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    arena: Res<Arena>,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<CrtMaterial>>,
+    mut cameras: Query<(Entity, &mut Camera, &mut Camera2d), With<GameCamera>>,
+    screens: Query<Entity, With<CrtScreen>>,
+) {
+    if !settings.is_changed() || settings.video.retro != screens.is_empty() {
+        return;
+    }
+
+    if !settings.video.retro {
+        for entity in screens.iter() {
+            commands.entity(entity).despawn();
+        }
+        for (entity, mut camera, mut camera_2d) in cameras.iter_mut() {
+            camera.target = RenderTarget::default();
+            camera_2d.clear_color = ClearColorConfig::Default;
+            commands.entity(entity).remove::<UiCameraConfig>();
+        }
+        return;
+    }
+
+    // the game is drawn at the arena's size and scaled up by the screen
+    let size = Extent3d {
+        width: arena.width as u32,
+        height: arena.height as u32,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::bevy_default(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    let screen = images.add(image);
+
+    for (entity, mut camera, mut camera_2d) in cameras.iter_mut() {
+        camera.target = RenderTarget::Image(screen.clone());
+        // the image always has the arena's shape, the screen does the letterboxing
+        camera.viewport = None;
+        // keep the old frame around for the background to fade out
+        camera_2d.clear_color = ClearColorConfig::None;
+        // the text stays crisp on top of the screen
+        commands
+            .entity(entity)
+            .insert(UiCameraConfig { show_ui: false });
+    }
+
+    commands
+        .spawn(Camera2dBundle {
+            camera: Camera {
+                priority: 1,
+                ..default()
+            },
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::Auto {
+                    min_width: arena.width,
+                    min_height: arena.height,
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(RenderLayers::layer(CRT_LAYER))
+        .insert(CrtScreen);
+
+    commands
+        .spawn(MaterialMesh2dBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Quad::new(Vec2::new(
+                    arena.width,
+                    arena.height,
+                ))))
+                .into(),
+            material: materials.add(CrtMaterial::new(screen)),
+            ..default()
+        })
+        .insert(RenderLayers::layer(CRT_LAYER))
+        .insert(CrtScreen);
+}
@@ -0,0 +1,75 @@
+// Draws the frame rendered by the game camera like an old vector monitor would
+
+// x: glow strength, y: glow radius in pixels, z: rings of glow samples, w: brightness
+@group(1) @binding(0)
+var<uniform> picture: vec4<f32>;
+// x: scanlines, y: curvature, z: vignette, w: shadow mask
+@group(1) @binding(1)
+var<uniform> tube: vec4<f32>;
+@group(1) @binding(2)
+var screen_texture: texture_2d<f32>;
+@group(1) @binding(3)
+var screen_sampler: sampler;
+
+// bend the picture outwards like the glass of a tube
+fn curve(uv: vec2<f32>) -> vec2<f32> {
+    let centered = uv * 2.0 - 1.0;
+    let bulge = centered.yx * centered.yx * tube.y;
+    return (centered + centered * bulge) * 0.5 + 0.5;
+}
+
+// explicit mip level so sampling is allowed after the early return below
+fn sample(coords: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(screen_texture, screen_sampler, coords, 0.0).rgb;
+}
+
+@fragment
+fn fragment(
+    @builtin(position) position: vec4<f32>,
+    #import bevy_sprite::mesh2d_vertex_output
+) -> @location(0) vec4<f32> {
+    let coords = curve(uv);
+    if (coords.x < 0.0 || coords.x > 1.0 || coords.y < 0.0 || coords.y > 1.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let size = vec2<f32>(textureDimensions(screen_texture));
+    var color = sample(coords);
+
+    // glow: rings of samples around the pixel, the closer ones counting the most
+    var glow = vec3<f32>(0.0);
+    var weight = 0.0;
+    let rings = i32(picture.z);
+    for (var ring = 1; ring <= rings; ring = ring + 1) {
+        let radius = picture.y * f32(ring) / f32(rings);
+        let falloff = 1.0 / f32(ring);
+        for (var i = 0; i < 8; i = i + 1) {
+            // turn every ring a bit so the samples don't line up
+            let angle = f32(i) * 0.785398 + f32(ring) * 0.392699;
+            let offset = vec2<f32>(cos(angle), sin(angle)) * radius / size;
+            glow = glow + sample(coords + offset) * falloff;
+            weight = weight + falloff;
+        }
+    }
+    if (weight > 0.0) {
+        color = color + glow / weight * picture.x;
+    }
+
+    // a dark line between every row of the source image
+    let scanline = 0.5 + 0.5 * cos(coords.y * size.y * 6.283185);
+    color = color * (1.0 - tube.x * scanline);
+
+    // the red, green and blue stripes of the shadow mask
+    let column = u32(position.x) % 3u;
+    let dim = 1.0 - tube.w;
+    color = color * vec3<f32>(
+        select(dim, 1.0, column == 0u),
+        select(dim, 1.0, column == 1u),
+        select(dim, 1.0, column == 2u),
+    );
+
+    // darker towards the corners of the tube
+    let edge = coords * (1.0 - coords.yx);
+    color = color * pow(clamp(edge.x * edge.y * 15.0, 0.0, 1.0), tube.z);
+
+    return vec4<f32>(color * picture.w, 1.0);
+}
@@ -0,0 +1,320 @@
+use crate::components::*;
+use crate::console::{self, AddConsoleCommand, CommandError};
+use crate::difficulty::GameDifficulty;
+use crate::modes::clock_text;
+use crate::resources::{GameMode, GameRng};
+use crate::settings::{Difficulty, Settings};
+use crate::storage;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const RESULTS_KEY: &str = "daily.toml";
+/// the share code of the latest daily run, to copy from
+const SHARE_KEY: &str = "daily-share.txt";
+/// how many modifiers each day's challenge has
+const MODIFIERS_PER_DAY: usize = 2;
+/// how much faster asteroids are with `Modifier::FastRocks`
+const FAST_ROCKS: f32 = 2.0;
+/// how much bigger asteroids are with `Modifier::HugeAsteroids`
+const HUGE_ASTEROIDS: f32 = 1.5;
+
+/// A daily challenge: the seed and a few modifiers come from the date, so everyone
+/// playing on the same day gets the same run. The best result of each day is kept,
+/// and can be shared as a line of text
+pub struct DailyPlugin;
+
+impl Plugin for DailyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DailyRun::default())
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(seed_run.label(SeedRun)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(apply_asteroid_modifiers),
+            )
+            .add_console_command("seed", "<number>", seed);
+
+        // the modifiers stay in place for the game over screen, and are gone by the
+        // time the title screen's demo plays
+        for state in [GameState::Title, GameState::MainMenu] {
+            app.add_system_set(SystemSet::on_enter(state).with_system(end_run));
+        }
+    }
+}
+
+/// Whatever starts the field of a new game comes after this, once the game's random
+/// numbers are seeded
+#[derive(SystemLabel)]
+pub struct SeedRun;
+
+/// a twist on the rules for the day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    /// the ship can only turn and shoot
+    NoThrust,
+    /// asteroids move at double speed
+    FastRocks,
+    /// asteroids are half as big again
+    HugeAsteroids,
+    /// the gun fires twice as fast
+    RapidFire,
+    /// every wave has two more asteroids
+    Swarm,
+}
+
+impl Modifier {
+    const ALL: [Modifier; 5] = [
+        Modifier::NoThrust,
+        Modifier::FastRocks,
+        Modifier::HugeAsteroids,
+        Modifier::RapidFire,
+        Modifier::Swarm,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Modifier::NoThrust => "No Thrust",
+            Modifier::FastRocks => "Fast Rocks",
+            Modifier::HugeAsteroids => "Huge Asteroids",
+            Modifier::RapidFire => "Rapid Fire",
+            Modifier::Swarm => "Swarm",
+        }
+    }
+}
+
+/// One day's challenge
+#[derive(Clone, Debug, PartialEq)]
+pub struct Challenge {
+    /// days since 1970-01-01, in UTC so the whole team is on the same run wherever
+    /// they are
+    pub day: u32,
+    pub seed: u64,
+    pub modifiers: Vec<Modifier>,
+}
+
+impl Challenge {
+    pub fn today() -> Self {
+        Self::for_day(today())
+    }
+
+    pub fn for_day(day: u32) -> Self {
+        let mut rng = StdRng::seed_from_u64(day as u64);
+        let modifiers = Modifier::ALL
+            .choose_multiple(&mut rng, MODIFIERS_PER_DAY)
+            .copied()
+            .collect();
+        Self {
+            day,
+            seed: rng.gen(),
+            modifiers,
+        }
+    }
+
+    /// YYYY-MM-DD
+    pub fn date(&self) -> String {
+        let (year, month, day) = civil_date(self.day);
+        format!("{}-{:02}-{:02}", year, month, day)
+    }
+
+    /// the modifiers, for people to read
+    pub fn describe(&self) -> String {
+        self.modifiers
+            .iter()
+            .map(Modifier::label)
+            .collect::<Vec<_>>()
+            .join(" + ")
+    }
+
+    /// A line of text with the day's best result, to paste in a chat
+    pub fn share_code(&self, result: &DailyResult) -> String {
+        format!(
+            "Asteroids daily {}: {} (level {}, {}, {} tries)",
+            self.date(),
+            result.score,
+            result.level,
+            clock_text(result.seconds),
+            result.attempts
+        )
+    }
+}
+
+/// The daily challenge being played, if the game is one
+#[derive(Resource, Default)]
+pub struct DailyRun(pub Option<Challenge>);
+
+impl DailyRun {
+    pub fn has(&self, modifier: Modifier) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|challenge| challenge.modifiers.contains(&modifier))
+    }
+}
+
+/// The best run of a day
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct DailyResult {
+    pub score: u32,
+    pub level: u32,
+    pub seconds: u32,
+    /// how many times the day's challenge was played
+    pub attempts: u32,
+}
+
+/// every day's result, by date
+#[derive(Serialize, Deserialize, Default)]
+struct DailyResults {
+    #[serde(default)]
+    days: BTreeMap<String, DailyResult>,
+}
+
+/// Keep a finished run of `challenge` if it is the best of the day, and write out the
+/// day's share code. Returns the day's result with this run counted
+pub fn record(challenge: &Challenge, score: u32, level: u32, seconds: u32) -> DailyResult {
+    let mut results: DailyResults = storage::load(RESULTS_KEY)
+        .and_then(|contents| match toml::from_str(&contents) {
+            Ok(results) => Some(results),
+            Err(error) => {
+                warn!("ignoring invalid daily results: {}", error);
+                None
+            }
+        })
+        .unwrap_or_default();
+
+    let result = results.days.entry(challenge.date()).or_default();
+    result.attempts += 1;
+    if score > result.score {
+        result.score = score;
+        result.level = level;
+        result.seconds = seconds;
+    }
+    let result = *result;
+
+    match toml::to_string(&results) {
+        Ok(contents) => storage::save(RESULTS_KEY, &contents),
+        Err(error) => warn!("could not serialize the daily results: {}", error),
+    }
+    storage::save(SHARE_KEY, &challenge.share_code(&result));
+    result
+}
+
+/// days since 1970-01-01 in UTC
+#[cfg(not(target_arch = "wasm32"))]
+fn today() -> u32 {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_secs() / 86_400) as u32
+}
+
+/// days since 1970-01-01 in UTC
+#[cfg(target_arch = "wasm32")]
+fn today() -> u32 {
+    (js_sys::Date::now() / 86_400_000.0) as u32
+}
+
+/// the year, month and day of a number of days since 1970-01-01, after Howard
+/// Hinnant's `civil_from_days`
+fn civil_date(days: u32) -> (i64, u32, u32) {
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // counting from March, so the leap day is at the end
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month as u32, day as u32)
+}
+
+/// Seed the new game's random numbers and pick its difficulty. A daily challenge
+/// takes its seed from the date and is played on Normal, whatever the settings say
+fn seed_run(
+    mode: Res<GameMode>,
+    settings: Res<Settings>,
+    mut daily: ResMut<DailyRun>,
+    mut rng: ResMut<GameRng>,
+    mut difficulty: ResMut<GameDifficulty>,
+) {
+    daily.0 = (*mode == GameMode::Daily).then(Challenge::today);
+    *rng = match &daily.0 {
+        Some(challenge) => GameRng::new(challenge.seed),
+        None => GameRng::default(),
+    };
+    difficulty.0 = match daily.0 {
+        Some(_) => Difficulty::Normal,
+        None => settings.gameplay.difficulty,
+    };
+}
+
+/// Reseed the game's random numbers, for the rest of this game; the next one picks
+/// its own seed again
+fn seed(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+    let seed: u64 = console::arg(args, 0)?;
+    *world.resource_mut::<GameRng>() = GameRng::new(seed);
+    Ok(format!("seeded with {}", seed))
+}
+
+fn end_run(
+    settings: Res<Settings>,
+    mut daily: ResMut<DailyRun>,
+    mut difficulty: ResMut<GameDifficulty>,
+) {
+    daily.0 = None;
+    difficulty.0 = settings.gameplay.difficulty;
+}
+
+/// asteroids are made faster and bigger as they come into play
+fn apply_asteroid_modifiers(
+    daily: Res<DailyRun>,
+    mut asteroids: Query<(&mut Transform, &mut Velocity), Added<Asteroid>>,
+) {
+    let fast = daily.has(Modifier::FastRocks);
+    let huge = daily.has(Modifier::HugeAsteroids);
+    if !fast && !huge {
+        return;
+    }
+    for (mut transform, mut velocity) in asteroids.iter_mut() {
+        if fast {
+            velocity.linvel *= FAST_ROCKS;
+        }
+        if huge {
+            transform.scale = Vec3::splat(HUGE_ASTEROIDS);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_date_starts_at_the_epoch() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+    }
+
+    #[test]
+    fn civil_date_has_leap_days() {
+        assert_eq!(civil_date(19_782), (2024, 2, 29));
+        assert_eq!(civil_date(19_783), (2024, 3, 1));
+    }
+
+    #[test]
+    fn challenge_is_the_same_every_time() {
+        let day = 19_782;
+        assert_eq!(Challenge::for_day(day), Challenge::for_day(day));
+        assert_eq!(Challenge::for_day(day).date(), "2024-02-29");
+        // a new day is a new run
+        assert_ne!(
+            Challenge::for_day(day).seed,
+            Challenge::for_day(day + 1).seed
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::camera::cursor_in_arena;
+use crate::components::*;
+use crate::pool;
+use crate::resources::{Arena, Cheated, Level};
+use crate::settings::launch_flag;
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use bevy::diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// how far ahead the velocity arrows point, in seconds of travel
+const ARROW_SECONDS: f32 = 0.5;
+/// how close a click has to be to something to pick it, past its own size
+const PICK_REACH: f32 = 20.0;
+
+/// A developer overlay, toggled with F3: frame rate, entity counts, the level and
+/// game state, the physics colliders' outlines and everything's velocity. Ctrl+click
+/// picks an entity to inspect, and its position, rotation, velocity and scale can then
+/// be changed while the game runs; a run changed that way isn't recorded, as with
+/// the console. Only in debug builds, or with `--debug`
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        if !cfg!(debug_assertions) && !launch_flag("debug") {
+            return;
+        }
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(RapierDebugRenderPlugin::default())
+            .insert_resource(DebugOverlay::default())
+            .add_startup_system(spawn_debug_text)
+            .add_system(toggle_overlay)
+            .add_system(pick_entity.after(toggle_overlay))
+            .add_system(edit_picked.after(pick_entity))
+            .add_system(draw_velocities.after(toggle_overlay))
+            .add_system(update_debug_text.after(edit_picked));
+    }
+}
+
+/// Whether the overlay is up, and what it is inspecting
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+    /// the entity picked for the inspector
+    picked: Option<Entity>,
+    /// which of `FIELDS` the inspector is changing
+    field: usize,
+    /// the lines drawn for velocities, reused from frame to frame
+    arrows: Vec<Entity>,
+}
+
+/// a value the inspector can change
+#[derive(Clone, Copy)]
+enum Field {
+    X,
+    Y,
+    Rotation,
+    VelocityX,
+    VelocityY,
+    Spin,
+    Scale,
+}
+
+const FIELDS: [Field; 7] = [
+    Field::X,
+    Field::Y,
+    Field::Rotation,
+    Field::VelocityX,
+    Field::VelocityY,
+    Field::Spin,
+    Field::Scale,
+];
+
+impl Field {
+    fn label(&self) -> &'static str {
+        match self {
+            Field::X => "x",
+            Field::Y => "y",
+            Field::Rotation => "rotation",
+            Field::VelocityX => "velocity x",
+            Field::VelocityY => "velocity y",
+            Field::Spin => "spin",
+            Field::Scale => "scale",
+        }
+    }
+
+    /// how much one press of - or = changes it by
+    fn step(&self) -> f32 {
+        match self {
+            Field::X | Field::Y | Field::VelocityX | Field::VelocityY => 10.0,
+            Field::Rotation => 15.0,
+            Field::Spin | Field::Scale => 0.1,
+        }
+    }
+
+    /// the value, with rotation in degrees
+    fn get(&self, transform: &Transform, velocity: &Velocity) -> f32 {
+        match self {
+            Field::X => transform.translation.x,
+            Field::Y => transform.translation.y,
+            Field::Rotation => {
+                let (_, _, angle) = transform.rotation.to_euler(EulerRot::XYZ);
+                angle.to_degrees()
+            }
+            Field::VelocityX => velocity.linvel.x,
+            Field::VelocityY => velocity.linvel.y,
+            Field::Spin => velocity.angvel,
+            Field::Scale => transform.scale.x,
+        }
+    }
+
+    fn set(&self, transform: &mut Transform, velocity: &mut Velocity, value: f32) {
+        match self {
+            Field::X => transform.translation.x = value,
+            Field::Y => transform.translation.y = value,
+            Field::Rotation => transform.rotation = Quat::from_rotation_z(value.to_radians()),
+            Field::VelocityX => velocity.linvel.x = value,
+            Field::VelocityY => velocity.linvel.y = value,
+            Field::Spin => velocity.angvel = value,
+            // a scale of zero would leave nothing to collide with
+            Field::Scale => transform.scale = Vec3::splat(value.max(0.1)),
+        }
+    }
+}
+
+fn spawn_debug_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 18.0,
+                    color: Color::YELLOW,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(20.0),
+                    top: Val::Px(50.0),
+                    ..default()
+                },
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(DebugText);
+}
+
+/// F3 shows or hides the overlay, the collider outlines with it
+fn toggle_overlay(
+    keyboard: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut render_context: ResMut<DebugRenderContext>,
+    mut text_query: Query<&mut Visibility, With<DebugText>>,
+) {
+    if keyboard.just_pressed(KeyCode::F3) {
+        overlay.enabled = !overlay.enabled;
+        overlay.picked = None;
+        for mut visibility in text_query.iter_mut() {
+            visibility.is_visible = overlay.enabled;
+        }
+    }
+    // the debug render plugin starts out drawing, whatever it is told
+    if render_context.enabled != overlay.enabled {
+        render_context.enabled = overlay.enabled;
+    }
+}
+
+/// Ctrl+click picks the nearest body under the cursor, or nothing
+#[allow(clippy::type_complexity)]
+fn pick_entity(
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    arena: Res<Arena>,
+    mut overlay: ResMut<DebugOverlay>,
+    cameras: Query<&Camera>,
+    bodies: Query<
+        (Entity, &Transform, Option<&Dimensions>),
+        (With<Velocity>, Without<Parked>, Without<Ghost>),
+    >,
+) {
+    if !overlay.enabled
+        || !keyboard.any_pressed([KeyCode::LControl, KeyCode::RControl])
+        || !buttons.just_pressed(MouseButton::Left)
+    {
+        return;
+    }
+    let Some(cursor) = cursor_in_arena(&windows, &arena, &cameras) else {
+        return;
+    };
+    let distance = |transform: &Transform| transform.translation.truncate().distance(cursor);
+    overlay.picked = bodies
+        .iter()
+        .filter(|(_, transform, dimensions)| {
+            let size = dimensions.map_or(0.0, |dimensions| {
+                dimensions.width.max(dimensions.height) / 2.0
+            });
+            distance(transform) < size * transform.scale.x + PICK_REACH
+        })
+        .min_by(|(_, a, _), (_, b, _)| distance(a).total_cmp(&distance(b)))
+        .map(|(entity, ..)| entity);
+    overlay.field = 0;
+}
+
+/// PageUp and PageDown pick a field, - and = change it (by ten times as much with
+/// shift), and Delete takes the entity out of the game, unless it is a ship
+fn edit_picked(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut cheated: ResMut<Cheated>,
+    mut bodies: Query<(&mut Transform, &mut Velocity), Without<Parked>>,
+    ships: Query<(), With<Ship>>,
+) {
+    let Some(picked) = overlay.picked.filter(|_| overlay.enabled) else {
+        return;
+    };
+    let Ok((mut transform, mut velocity)) = bodies.get_mut(picked) else {
+        // destroyed, or put back in the pool
+        overlay.picked = None;
+        return;
+    };
+
+    if keyboard.just_pressed(KeyCode::PageDown) {
+        overlay.field = (overlay.field + 1) % FIELDS.len();
+    }
+    if keyboard.just_pressed(KeyCode::PageUp) {
+        overlay.field = (overlay.field + FIELDS.len() - 1) % FIELDS.len();
+    }
+
+    let field = FIELDS[overlay.field];
+    let mut step = field.step();
+    if keyboard.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+        step *= 10.0;
+    }
+    let change = if keyboard.just_pressed(KeyCode::Equals) {
+        step
+    } else if keyboard.just_pressed(KeyCode::Minus) {
+        -step
+    } else {
+        0.0
+    };
+    if change != 0.0 {
+        let value = field.get(&transform, &velocity) + change;
+        field.set(&mut transform, &mut velocity, value);
+        cheated.0 = true;
+    }
+
+    // the game has no way of carrying on without a ship it did not destroy itself
+    if keyboard.just_pressed(KeyCode::Delete) && !ships.contains(picked) {
+        pool::release(&mut commands, picked);
+        overlay.picked = None;
+        cheated.0 = true;
+    }
+}
+
+/// A line from each moving body to where it will be in `ARROW_SECONDS`
+#[allow(clippy::type_complexity)]
+fn draw_velocities(
+    mut commands: Commands,
+    shapes: Res<ShapeCache>,
+    mut overlay: ResMut<DebugOverlay>,
+    bodies: Query<(&Transform, &Velocity), (Without<Parked>, Without<Ghost>)>,
+    mut arrows: Query<(&mut Transform, &mut Visibility), (With<VelocityArrow>, Without<Velocity>)>,
+) {
+    let mut shown = 0;
+    if overlay.enabled {
+        for (transform, velocity) in bodies.iter() {
+            let line = velocity.linvel * ARROW_SECONDS;
+            if line.length_squared() < 1.0 {
+                continue;
+            }
+            let start = transform.translation.truncate();
+            let arrow_transform = Transform {
+                translation: (start + line / 2.0).extend(2.0),
+                rotation: Quat::from_rotation_z(line.y.atan2(line.x)),
+                scale: Vec3::new(line.length(), 1.0, 1.0),
+            };
+            match overlay.arrows.get(shown) {
+                Some(&arrow) => {
+                    if let Ok((mut transform, mut visibility)) = arrows.get_mut(arrow) {
+                        *transform = arrow_transform;
+                        visibility.is_visible = true;
+                    }
+                }
+                None => {
+                    let arrow = commands
+                        .spawn(shapes.bundle(ShapeKind::Debris, arrow_transform))
+                        .insert(VelocityArrow)
+                        .id();
+                    overlay.arrows.push(arrow);
+                }
+            }
+            shown += 1;
+        }
+    }
+
+    // the arrows left over this frame
+    for &arrow in &overlay.arrows[shown.min(overlay.arrows.len())..] {
+        if let Ok((_, mut visibility)) = arrows.get_mut(arrow) {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_debug_text(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    level: Res<Level>,
+    state: Res<State<GameState>>,
+    entities: Query<()>,
+    counts: Query<(
+        Option<&Asteroid>,
+        Option<&Laser>,
+        Option<&Ship>,
+        Option<&Wrapper>,
+    )>,
+    picked: Query<(
+        &Transform,
+        &Velocity,
+        Option<&Asteroid>,
+        Option<&Laser>,
+        Option<&Player>,
+        Option<&Ufo>,
+    )>,
+    mut text_query: Query<&mut Text, With<DebugText>>,
+) {
+    if !overlay.enabled {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or_default();
+    let (mut asteroids, mut lasers, mut ships, mut wrappers) = (0, 0, 0, 0);
+    for (asteroid, laser, ship, wrapper) in counts.iter() {
+        asteroids += asteroid.is_some() as u32;
+        lasers += laser.is_some() as u32;
+        ships += ship.is_some() as u32;
+        wrappers += wrapper.is_some() as u32;
+    }
+
+    let mut lines = vec![
+        format!("FPS {:.0}", fps),
+        format!("Entities {}", entities.iter().count()),
+        format!(
+            "Asteroids {}  Lasers {}  Ships {}  Wrappers {}",
+            asteroids, lasers, ships, wrappers
+        ),
+        format!("State {:?}  Level {}", state.current(), level.0),
+    ];
+    match overlay
+        .picked
+        .and_then(|entity| Some((entity, picked.get(entity).ok()?)))
+    {
+        Some((entity, (transform, velocity, asteroid, laser, player, ufo))) => {
+            let kind = match (asteroid, laser, player, ufo) {
+                (Some(asteroid), ..) => format!("Asteroid {:?}", asteroid.0),
+                (_, Some(laser), ..) => format!("Laser of P{}", laser.owner.0 + 1),
+                (_, _, Some(player), _) => format!("Ship P{}", player.0 + 1),
+                (.., Some(_)) => "UFO".to_string(),
+                _ => "Body".to_string(),
+            };
+            lines.push(String::new());
+            lines.push(format!("{:?} {}", entity, kind));
+            for (index, field) in FIELDS.iter().enumerate() {
+                let marker = if index == overlay.field { ">" } else { " " };
+                lines.push(format!(
+                    "{} {} {:.1}",
+                    marker,
+                    field.label(),
+                    field.get(transform, velocity)
+                ));
+            }
+            lines.push("[PgUp/PgDn] field  [-/=] change  [Del] remove".to_string());
+        }
+        None => lines.push("[Ctrl+click] inspect".to_string()),
+    }
+
+    let value = lines.join("\n");
+    for mut text in text_query.iter_mut() {
+        // only touch the text when it changed so it isn't laid out every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::settings::Difficulty;
+use bevy::prelude::*;
+
+/// The numbers a difficulty setting controls
+pub struct DifficultyPreset {
+    /// minimum and maximum speed of a new asteroid
+    pub asteroid_speed: (f32, f32),
+    /// seconds between laser shots
+    pub fire_cooldown: f32,
+    /// impulse applied each frame the ship thrusts
+    pub thrust: f32,
+    /// how quickly the ship slows down when not thrusting
+    pub damping: f32,
+    pub starting_lives: u32,
+    /// 0.0 to 1.0. How often UFOs show up, how often they shoot and how well they aim
+    pub ufo_aggression: f32,
+}
+
+/// The difficulty the game being played goes by: the one picked in the settings, but
+/// Normal for a daily challenge so everyone gets the same run
+#[derive(Resource, Default)]
+pub struct GameDifficulty(pub Difficulty);
+
+impl GameDifficulty {
+    pub fn preset(&self) -> DifficultyPreset {
+        self.0.preset()
+    }
+}
+
+impl Difficulty {
+    pub fn preset(&self) -> DifficultyPreset {
+        match self {
+            Difficulty::Easy => DifficultyPreset {
+                asteroid_speed: (20.0, 70.0),
+                fire_cooldown: 0.15,
+                thrust: 9000.0,
+                damping: 1.0,
+                starting_lives: 5,
+                ufo_aggression: 0.2,
+            },
+            Difficulty::Normal => DifficultyPreset {
+                asteroid_speed: (30.0, 100.0),
+                fire_cooldown: 0.2,
+                thrust: 10000.0,
+                damping: 0.5,
+                starting_lives: 3,
+                ufo_aggression: 0.5,
+            },
+            Difficulty::Hard => DifficultyPreset {
+                asteroid_speed: (50.0, 150.0),
+                fire_cooldown: 0.25,
+                thrust: 11000.0,
+                damping: 0.3,
+                starting_lives: 2,
+                ufo_aggression: 0.8,
+            },
+            // fast rocks, a slow gun and a mean saucer
+            Difficulty::Arcade => DifficultyPreset {
+                asteroid_speed: (60.0, 180.0),
+                fire_cooldown: 0.3,
+                thrust: 12000.0,
+                damping: 0.2,
+                starting_lives: 3,
+                ufo_aggression: 1.0,
+            },
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::components::{AsteroidSize, Player};
+use bevy::prelude::{App, Plugin, Quat, Vec2};
+
+/// The gameplay events. The core systems only report what happened through these;
+/// scoring, effects and the like each listen for the ones they care about
+pub struct EventsPlugin;
+
+impl Plugin for EventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AsteroidDestroyed>()
+            .add_event::<ShipDestroyed>()
+            .add_event::<UfoDestroyed>()
+            .add_event::<LaserFired>()
+            .add_event::<LevelCleared>();
+    }
+}
+
+/// an asteroid was shot. It has already been despawned
+pub struct AsteroidDestroyed {
+    pub size: AsteroidSize,
+    pub position: Vec2,
+    /// how fast it was moving; the pieces keep going that way
+    pub velocity: Vec2,
+    pub by: Player,
+}
+
+/// the ship ran into something
+pub struct ShipDestroyed {
+    pub player: Player,
+    /// whoever shot it, in versus
+    pub by: Option<Player>,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// which way it was facing, to break it apart along its edges
+    pub rotation: Quat,
+}
+
+/// the player shot down the saucer
+pub struct UfoDestroyed {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub by: Player,
+}
+
+/// the ship fired a laser
+pub struct LaserFired;
+
+/// the last asteroid of a level is gone
+pub struct LevelCleared;
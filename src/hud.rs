@@ -0,0 +1,133 @@
+use crate::components::*;
+use crate::modes::{clock_text, GameClock, TimeAttack};
+use crate::resources::*;
+use bevy::prelude::*;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_hud))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_hud))
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_hud));
+    }
+}
+
+/// Show the level, everyone's score and lives (and the clock, in the modes played
+/// against it) in the top left corner
+fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 30.0,
+                    color: Color::WHITE,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(20.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Hud);
+}
+
+fn update_hud(
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    level: Res<Level>,
+    rounds: Res<Rounds>,
+    clock: Res<GameClock>,
+    time_attack: Res<TimeAttack>,
+    mut query: Query<&mut Text, With<Hud>>,
+) {
+    let elapsed = clock_text(clock.0.elapsed_secs() as u32);
+    // with more than one player, each one's score and lives are in their own color
+    let values: Vec<(String, Color)> = match players.0.as_slice() {
+        // versus is about kills and rounds won instead
+        players if *mode == GameMode::Versus => {
+            std::iter::once((format!("Round {}", rounds.number), Color::WHITE))
+                .chain(players.iter().enumerate().map(|(id, player)| {
+                    (
+                        format!(
+                            "    P{} Kills {}  Wins {}",
+                            id + 1,
+                            player.score,
+                            rounds.wins.get(id).copied().unwrap_or_default()
+                        ),
+                        Player(id).color(),
+                    )
+                }))
+                .collect()
+        }
+        [player] => {
+            let value = match *mode {
+                GameMode::TimeAttack => {
+                    // counting down, 0:00 only once it has run out
+                    let remaining = time_attack.remaining(&clock).as_secs_f32().ceil();
+                    format!(
+                        "Score {}    Waves cleared {}    Time {}",
+                        player.score,
+                        level.waves_cleared(),
+                        clock_text(remaining as u32)
+                    )
+                }
+                GameMode::Survival => format!(
+                    "Score {}    Time {}    Lives {}",
+                    player.score, elapsed, player.lives
+                ),
+                GameMode::OneLife => {
+                    format!(
+                        "Score {}    Level {}    Time {}",
+                        player.score, level.0, elapsed
+                    )
+                }
+                _ => format!(
+                    "Score {}    Level {}    Lives {}",
+                    player.score, level.0, player.lives
+                ),
+            };
+            vec![(value, Color::WHITE)]
+        }
+        players => std::iter::once((format!("Level {}", level.0), Color::WHITE))
+            .chain(players.iter().enumerate().map(|(id, player)| {
+                (
+                    format!("    P{} {}  Lives {}", id + 1, player.score, player.lives),
+                    Player(id).color(),
+                )
+            }))
+            .collect(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections.len() != values.len() {
+            let style = text.sections[0].style.clone();
+            text.sections = values
+                .iter()
+                .map(|(value, _)| TextSection::new(value.clone(), style.clone()))
+                .collect();
+        }
+        // only touch the text when it changed so it isn't laid out every frame
+        for (index, (value, color)) in values.iter().enumerate() {
+            let section = &text.sections[index];
+            if section.value != *value || section.style.color != *color {
+                let section = &mut text.sections[index];
+                section.value = value.clone();
+                section.style.color = *color;
+            }
+        }
+    }
+}
+
+fn remove_hud(mut commands: Commands, query: Query<Entity, With<Hud>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
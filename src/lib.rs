@@ -0,0 +1,34 @@
+//! The game's plugins and everything they are built from. The binary puts them
+//! together into the app; the benches use the pieces directly
+
+pub mod asteroid;
+pub mod attract;
+pub mod autopilot;
+pub mod camera;
+pub mod collision;
+pub mod components;
+pub mod console;
+pub mod crt;
+pub mod daily;
+pub mod debug;
+pub mod difficulty;
+pub mod events;
+pub mod hud;
+pub mod menu;
+pub mod modes;
+pub mod netplay;
+pub mod particles;
+pub mod pool;
+pub mod resources;
+pub mod sandbox;
+pub mod savegame;
+pub mod score;
+pub mod settings;
+pub mod shape_cache;
+pub mod ship;
+pub mod sound;
+pub mod storage;
+pub mod stress;
+pub mod ufo;
+pub mod versus;
+pub mod wrap;
@@ -1,140 +1,189 @@
-use crate::components::*;
+use asteroids::components::*;
+use asteroids::daily::{self, SeedRun};
+use asteroids::difficulty::GameDifficulty;
+use asteroids::resources::{
+    Arena, Cheated, GameMode, GameRng, GodMode, HighScores, Level, Players, PowerUps, Rounds,
+};
+use asteroids::settings::{self, Settings};
+use asteroids::{
+    asteroid, attract, autopilot, camera, collision, console, crt, debug, events, hud, menu, modes,
+    netplay, particles, pool, sandbox, savegame, score, shape_cache, ship, sound, stress, ufo,
+    versus, wrap,
+};
 use bevy::prelude::*;
+use bevy::render::camera::{RenderTarget, ScalingMode, Viewport};
 use bevy_prototype_lyon::prelude::*;
 use bevy_rapier2d::prelude::*;
-use resources::{FireTimer, Level, FILL_COLOR};
-
-mod asteroid;
-mod components;
-mod resources;
-mod ship;
 
 fn main() {
+    let settings = Settings::load();
+
     App::new()
-        // Set the background color
-        .insert_resource(ClearColor(FILL_COLOR))
-        .insert_resource(Msaa { samples: 4 })
+        // the letterbox bars around the arena
+        .insert_resource(ClearColor(Color::BLACK))
+        .insert_resource(Msaa {
+            samples: settings.video.msaa,
+        })
+        .insert_resource(Arena::default())
         .insert_resource(Level::default())
-        .insert_resource(FireTimer::default())
-        .add_plugins(DefaultPlugins)
+        .insert_resource(GameMode::default())
+        .insert_resource(Players::default())
+        .insert_resource(Rounds::default())
+        .insert_resource(GameRng::default())
+        .insert_resource(PowerUps::default())
+        .insert_resource(GodMode::default())
+        .insert_resource(GameDifficulty::default())
+        .insert_resource(Cheated::default())
+        .insert_resource(HighScores::default())
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            window: WindowDescriptor {
+                title: "Asteroids".to_string(),
+                width: settings.video.width,
+                height: settings.video.height,
+                mode: settings.window_mode(),
+                fit_canvas_to_parent: true,
+                ..default()
+            },
+            ..default()
+        }))
+        // before the plugins, some of which start from the settings
+        .insert_resource(settings)
+        .add_plugin(events::EventsPlugin)
         .add_plugin(ShapePlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugin(collision::CollisionPlugin)
+        .add_plugin(shape_cache::ShapeCachePlugin)
+        .add_plugin(pool::PoolPlugin)
         .add_plugin(ship::ShipPlugin)
+        .add_plugin(autopilot::AutopilotPlugin)
+        .add_plugin(score::ScorePlugin)
         .add_plugin(asteroid::AsteroidPlugin)
-        .add_state(GameState::GameOver)
-        .add_system(bevy::window::close_on_esc)
+        .add_plugin(menu::MenuPlugin)
+        .add_plugin(attract::AttractPlugin)
+        .add_plugin(ufo::UfoPlugin)
+        .add_plugin(versus::VersusPlugin)
+        .add_plugin(modes::ModesPlugin)
+        .add_plugin(daily::DailyPlugin)
+        .add_plugin(sandbox::SandboxPlugin)
+        .add_plugin(hud::HudPlugin)
+        .add_plugin(savegame::SaveGamePlugin)
+        .add_plugin(settings::SettingsPlugin)
+        .add_plugin(wrap::WrapPlugin)
+        .add_plugin(particles::ParticlePlugin)
+        .add_plugin(sound::SoundPlugin)
+        .add_plugin(crt::CrtPlugin)
+        .add_plugin(camera::CameraEffectsPlugin)
+        .add_plugin(stress::StressPlugin)
+        .add_plugin(netplay::NetplayPlugin)
+        .add_plugin(debug::DebugPlugin)
+        .add_plugin(console::ConsolePlugin)
+        .add_state(GameState::Title)
         .add_startup_system(spawn_camera)
+        .add_system(fit_camera_to_window)
         .add_system_set(
-            SystemSet::on_enter(GameState::GameOver)
-                .with_system(spawn_instructions)
-                .with_system(instructions_input),
-        )
-        .add_system_set(
-            SystemSet::on_update(GameState::GameOver)
-                .with_system(instructions_input)
-                .with_system(wrap_mover),
-        )
-        .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(enter_playing))
-        .add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(playing_input)
-                .with_system(wrap_mover),
+            SystemSet::on_enter(GameState::Playing).with_system(enter_playing.after(SeedRun)),
         )
+        .add_system_set(SystemSet::on_update(GameState::Playing).with_system(playing_input))
         .run();
 }
 
-/// Setup the game camera
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
-}
-
-/// Show the "Press Space to start" prompt when the game is launched
-fn spawn_instructions(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// Setup the game camera so it always shows exactly the arena, plus the arena's
+/// background. Anything outside the arena is the (black) clear color
+fn spawn_camera(mut commands: Commands, arena: Res<Arena>, settings: Res<Settings>) {
     commands
-        .spawn(TextBundle {
-            text: Text::from_section(
-                "Press Space to start\nWhile playing, press R to reset",
-                TextStyle {
-                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                    font_size: 40.0,
-                    color: Color::WHITE,
+        .spawn(Camera2dBundle {
+            projection: OrthographicProjection {
+                scaling_mode: ScalingMode::Auto {
+                    min_width: arena.width,
+                    min_height: arena.height,
                 },
-            ),
-            style: Style {
-                align_self: AlignSelf::Center,
-                margin: UiRect {
-                    left: Val::Px(100.0),
-                    ..default()
-                },
-                ..Default::default()
+                ..default()
             },
-            ..Default::default()
+            ..default()
         })
-        .insert(components::Instructions);
-}
+        .insert(GameCamera);
 
-// when the game starts (by the GameState changing), removing the instructinos
-fn enter_playing(
-    mut commands: Commands,
-    mut level: ResMut<Level>,
-    query: Query<Entity, With<components::Instructions>>,
-) {
-    // reset level
-    level.0 = 1;
-
-    for entity in query.iter() {
-        commands.entity(entity).despawn();
-    }
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: settings.background_color(),
+                custom_size: Some(Vec2::new(arena.width, arena.height)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, -10.0),
+            ..default()
+        })
+        .insert(ArenaBackground);
 }
 
-/// enter "Playing" state when user presses space
-fn instructions_input(
-    mut state: ResMut<State<GameState>>,
-    mut keyboard_input: ResMut<Input<KeyCode>>,
-) {
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        state.set(GameState::Playing).unwrap();
-        keyboard_input.reset(KeyCode::Space);
+/// Letterbox the camera: render into the largest rectangle with the arena's aspect
+/// ratio that fits in the window, centered
+fn fit_camera_to_window(windows: Res<Windows>, arena: Res<Arena>, mut cameras: Query<&mut Camera>) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    if window_size.x == 0 || window_size.y == 0 {
+        // minimized
+        return;
     }
-}
 
-/// while in the "Playing" state, reset the game when user presses R
-fn playing_input(mut state: ResMut<State<GameState>>, mut keyboard_input: ResMut<Input<KeyCode>>) {
-    if keyboard_input.just_pressed(KeyCode::R) {
-        state.set(GameState::GameOver).unwrap();
-        keyboard_input.reset(KeyCode::R);
+    let scale = (window_size.x as f32 / arena.width).min(window_size.y as f32 / arena.height);
+    let size = (Vec2::new(arena.width, arena.height) * scale)
+        .as_uvec2()
+        .min(window_size);
+    let viewport = Viewport {
+        physical_position: (window_size - size) / 2,
+        physical_size: size,
+        ..default()
+    };
+
+    for mut camera in cameras.iter_mut() {
+        // cameras drawing into an image (like the retro display's) are left alone
+        if !matches!(camera.target, RenderTarget::Window(_)) {
+            continue;
+        }
+        let unchanged = matches!(&camera.viewport, Some(current)
+            if current.physical_position == viewport.physical_position
+                && current.physical_size == viewport.physical_size);
+        if !unchanged {
+            camera.viewport = Some(viewport.clone());
+        }
     }
 }
 
-/// Wrap the ship/asteroids around the screen when it goes off the edge
-fn wrap_mover(
-    windows: Res<Windows>,
-    mut query: Query<(&Dimensions, &mut Transform), With<Wrapper>>,
+// when the game starts (by the GameState changing), reset the level, everyone's
+// score and lives, and any power-ups
+#[allow(clippy::too_many_arguments)]
+fn enter_playing(
+    difficulty: Res<GameDifficulty>,
+    mode: Res<GameMode>,
+    one_life: Res<modes::OneLife>,
+    mut level: ResMut<Level>,
+    mut players: ResMut<Players>,
+    mut power_ups: ResMut<PowerUps>,
+    mut god_mode: ResMut<GodMode>,
+    mut cheated: ResMut<Cheated>,
 ) {
-    for (dimensions, mut transform) in query.iter_mut() {
-        let window = windows.get_primary().unwrap();
-        // World coordinates are in the center, so we get half the window dimensions
-        // to figure out the actual edges of the screen
-        let window_width = window.width() / 2.0;
-        let window_height = window.height() / 2.0;
-
-        // get current position from transform
-        let mut position = transform.translation;
-        let x_offset = dimensions.width / 2.0;
-        let y_offset = dimensions.height / 2.0;
-
-        if position.x > window_width + x_offset {
-            position.x = -window_width - x_offset;
-        } else if position.x < -window_width - x_offset {
-            position.x = window_width + x_offset;
-        }
-
-        if position.y > window_height + y_offset {
-            position.y = -window_height - y_offset;
-        } else if position.y < -window_height - y_offset {
-            position.y = window_height + y_offset;
-        }
+    level.0 = 1;
+    *power_ups = PowerUps::default();
+    god_mode.0 = false;
+    cheated.0 = false;
+    let lives = match *mode {
+        GameMode::OneLife => one_life.lives,
+        _ => difficulty.preset().starting_lives,
+    };
+    *players = Players::new(mode.players(), lives);
+}
 
-        transform.translation = position;
+/// while in the "Playing" state, reset the game when user presses R and pause
+/// it on escape/start
+fn playing_input(mut state: ResMut<State<GameState>>, mut input: menu::MenuInput) {
+    if input.pause() {
+        state.push(GameState::Paused).unwrap();
+        input.clear();
+    } else if input.reset() {
+        state.set(GameState::GameOver).unwrap();
+        input.clear();
     }
 }
@@ -0,0 +1,595 @@
+use crate::components::*;
+use crate::daily::{self, Challenge, DailyRun};
+use crate::difficulty::GameDifficulty;
+use crate::modes::{clock_text, GameClock};
+use crate::resources::*;
+use crate::savegame::{can_continue, PendingRun, SaveGame};
+use crate::settings::{SettingField, Settings};
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::prelude::*;
+use std::marker::PhantomData;
+
+/// the game over screen's note that a run with console commands wasn't recorded
+const CHEATED: &str = "Console commands were used: not recorded";
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MenuCursor::default())
+            .insert_resource(ShownScoreTable::default())
+            .add_system_set(SystemSet::on_enter(GameState::Title).with_system(spawn_title))
+            .add_system_set(SystemSet::on_update(GameState::Title).with_system(title_input))
+            .add_system_set(SystemSet::on_exit(GameState::Title).with_system(remove_menu))
+            .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(spawn_main_menu))
+            .add_system_set(
+                SystemSet::on_enter(GameState::ModeSelect).with_system(spawn_mode_select),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::HighScores).with_system(spawn_high_scores),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::HighScores).with_system(flip_score_table),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused)
+                    .with_system(spawn_pause_menu)
+                    .with_system(pause_physics),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(resume_physics))
+            .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(spawn_game_over));
+
+        for state in [
+            GameState::MainMenu,
+            GameState::ModeSelect,
+            GameState::Settings,
+            GameState::HighScores,
+            GameState::Paused,
+            GameState::GameOver,
+        ] {
+            app.add_system_set(build_update_system_set(state))
+                .add_system_set(SystemSet::on_exit(state).with_system(remove_menu));
+        }
+    }
+}
+
+fn build_update_system_set(state: GameState) -> SystemSet {
+    SystemSet::on_update(state)
+        .with_system(menu_navigation)
+        .with_system(highlight_selected.after(menu_navigation))
+}
+
+/// Keyboard and gamepad buttons used to drive the menus
+#[derive(SystemParam)]
+pub struct MenuInput<'w, 's> {
+    keyboard: ResMut<'w, Input<KeyCode>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: ResMut<'w, Input<GamepadButton>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> MenuInput<'w, 's> {
+    fn pressed(&self, keys: &[KeyCode], buttons: &[GamepadButtonType]) -> bool {
+        self.keyboard.any_just_pressed(keys.iter().copied())
+            || self.gamepads.iter().any(|gamepad| {
+                self.buttons.any_just_pressed(
+                    buttons
+                        .iter()
+                        .map(|button| GamepadButton::new(gamepad, *button)),
+                )
+            })
+    }
+
+    pub fn up(&self) -> bool {
+        self.pressed(&[KeyCode::Up, KeyCode::W], &[GamepadButtonType::DPadUp])
+    }
+
+    pub fn down(&self) -> bool {
+        self.pressed(&[KeyCode::Down, KeyCode::S], &[GamepadButtonType::DPadDown])
+    }
+
+    pub fn left(&self) -> bool {
+        self.pressed(&[KeyCode::Left, KeyCode::A], &[GamepadButtonType::DPadLeft])
+    }
+
+    pub fn right(&self) -> bool {
+        self.pressed(
+            &[KeyCode::Right, KeyCode::D],
+            &[GamepadButtonType::DPadRight],
+        )
+    }
+
+    pub fn confirm(&self) -> bool {
+        self.pressed(
+            &[KeyCode::Return, KeyCode::Space],
+            &[GamepadButtonType::South],
+        )
+    }
+
+    pub fn back(&self) -> bool {
+        self.pressed(
+            &[KeyCode::Escape, KeyCode::Back],
+            &[GamepadButtonType::East, GamepadButtonType::Start],
+        )
+    }
+
+    /// escape or start, used to bring up the pause menu
+    pub fn pause(&self) -> bool {
+        self.pressed(&[KeyCode::Escape], &[GamepadButtonType::Start])
+    }
+
+    /// R restarts a game in progress
+    pub fn reset(&self) -> bool {
+        self.pressed(&[KeyCode::R], &[GamepadButtonType::Select])
+    }
+
+    pub fn any(&self) -> bool {
+        self.keyboard.get_just_pressed().len() > 0 || self.buttons.get_just_pressed().len() > 0
+    }
+
+    /// Forget this frame's presses so the next state doesn't act on them too
+    pub fn clear(&mut self) {
+        self.keyboard.clear();
+        self.buttons.clear();
+    }
+}
+
+/// Spawn a centered column with a title, some informational lines and the
+/// selectable items of a menu
+pub fn spawn_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    title: &str,
+    lines: &[String],
+    items: &[(&str, MenuAction)],
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text = |value: &str, font_size: f32, color: Color| TextBundle {
+        text: Text::from_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color,
+            },
+        ),
+        style: Style {
+            margin: UiRect::all(Val::Px(8.0)),
+            ..default()
+        },
+        ..default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(Menu)
+        .with_children(|parent| {
+            parent.spawn(text(title, 60.0, Color::WHITE));
+            for line in lines {
+                parent.spawn(text(line, 30.0, Color::GRAY));
+            }
+            for (index, (label, action)) in items.iter().enumerate() {
+                parent
+                    .spawn(text(label, 40.0, Color::GRAY))
+                    .insert(MenuItem {
+                        index,
+                        action: *action,
+                    });
+            }
+        });
+}
+
+fn spawn_title(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_title_page(&mut commands, &asset_server);
+}
+
+pub fn spawn_title_page(commands: &mut Commands, asset_server: &AssetServer) {
+    spawn_menu(
+        commands,
+        asset_server,
+        "ASTEROIDS",
+        &["Press any key".to_string()],
+        &[],
+    );
+}
+
+fn spawn_main_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+) {
+    cursor.0 = 0;
+    let mut items = Vec::new();
+    if can_continue() {
+        items.push(("Continue", MenuAction::Continue));
+    }
+    items.extend([
+        ("Play", MenuAction::Goto(GameState::ModeSelect)),
+        ("High Scores", MenuAction::Goto(GameState::HighScores)),
+        ("Settings", MenuAction::Goto(GameState::Settings)),
+        // there is nothing to quit to in the browser
+        #[cfg(not(target_arch = "wasm32"))]
+        ("Quit", MenuAction::Quit),
+    ]);
+    spawn_menu(&mut commands, &asset_server, "ASTEROIDS", &[], &items);
+}
+
+fn spawn_mode_select(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+    settings: Res<Settings>,
+) {
+    cursor.0 = 0;
+    let difficulty = settings.label(SettingField::Difficulty);
+    let today = Challenge::today();
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "SELECT MODE",
+        &[format!("Today's challenge: {}", today.describe())],
+        &[
+            ("Classic", MenuAction::Start(GameMode::Classic)),
+            ("Time Attack", MenuAction::Start(GameMode::TimeAttack)),
+            ("Survival", MenuAction::Start(GameMode::Survival)),
+            ("One Life", MenuAction::Start(GameMode::OneLife)),
+            ("Daily Challenge", MenuAction::Start(GameMode::Daily)),
+            ("Sandbox", MenuAction::Start(GameMode::Sandbox)),
+            ("Co-op", MenuAction::Start(GameMode::Coop)),
+            ("Versus", MenuAction::Start(GameMode::Versus)),
+            (&difficulty, MenuAction::Adjust(SettingField::Difficulty)),
+            ("Back", MenuAction::Goto(GameState::MainMenu)),
+        ],
+    );
+}
+
+/// The high score table on show, as an index into `GameMode::SCORE_TABLES`
+#[derive(Resource, Default)]
+struct ShownScoreTable(usize);
+
+fn spawn_high_scores(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+    high_scores: Res<HighScores>,
+    shown: Res<ShownScoreTable>,
+) {
+    cursor.0 = 0;
+    spawn_high_score_page(&mut commands, &asset_server, &high_scores, &shown);
+}
+
+fn spawn_high_score_page(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    high_scores: &HighScores,
+    shown: &ShownScoreTable,
+) {
+    let table = GameMode::SCORE_TABLES[shown.0];
+    let mut lines = vec![format!("<  {}  >", table.label())];
+    lines.extend(high_score_lines(high_scores, table));
+    spawn_menu(
+        commands,
+        asset_server,
+        "HIGH SCORES",
+        &lines,
+        &[("Back", MenuAction::Goto(GameState::MainMenu))],
+    );
+}
+
+/// left and right go through the tables of the different modes
+fn flip_score_table(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    input: MenuInput,
+    high_scores: Res<HighScores>,
+    mut shown: ResMut<ShownScoreTable>,
+    menus: Query<Entity, With<Menu>>,
+) {
+    let count = GameMode::SCORE_TABLES.len();
+    if input.left() {
+        shown.0 = (shown.0 + count - 1) % count;
+    } else if input.right() {
+        shown.0 = (shown.0 + 1) % count;
+    } else {
+        return;
+    }
+    for entity in menus.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_high_score_page(&mut commands, &asset_server, &high_scores, &shown);
+}
+
+/// one line per entry of a table
+pub fn high_score_lines(high_scores: &HighScores, table: GameMode) -> Vec<String> {
+    let mut lines: Vec<String> = high_scores
+        .table(table)
+        .iter()
+        .enumerate()
+        .map(|(rank, entry)| {
+            // what the table is ranked by first, then the rest of the game
+            let (first, second) = match table {
+                GameMode::TimeAttack => (
+                    format!("{} waves", entry.waves_cleared),
+                    entry.score.to_string(),
+                ),
+                GameMode::Survival => (clock_text(entry.seconds), entry.score.to_string()),
+                _ => (entry.score.to_string(), format!("level {}", entry.level)),
+            };
+            format!(
+                "{:>2}.  {:>8}  {:<10} {:?}",
+                rank + 1,
+                first,
+                second,
+                entry.difficulty
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("No scores yet".to_string());
+    }
+    lines
+}
+
+fn spawn_pause_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+) {
+    cursor.0 = 0;
+    spawn_menu(
+        &mut commands,
+        &asset_server,
+        "PAUSED",
+        &[],
+        &[
+            ("Resume", MenuAction::Resume),
+            ("Quit to Menu", MenuAction::Goto(GameState::MainMenu)),
+        ],
+    );
+}
+
+/// Record everyone's final score and show them. A versus match shows who won instead,
+/// as kills don't go in the high score table, and a daily challenge how the day's
+/// runs went
+#[allow(clippy::too_many_arguments)]
+fn spawn_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+    difficulty: Res<GameDifficulty>,
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    rounds: Res<Rounds>,
+    level: Res<Level>,
+    clock: Res<GameClock>,
+    daily: Res<DailyRun>,
+    cheated: Res<Cheated>,
+    mut high_scores: ResMut<HighScores>,
+    mut shown: ResMut<ShownScoreTable>,
+) {
+    cursor.0 = 0;
+    let seconds = clock.0.elapsed_secs() as u32;
+    if *mode == GameMode::Versus {
+        // a match quit early can be level
+        let best = rounds.wins.iter().max().copied().unwrap_or_default();
+        let leaders: Vec<usize> = (0..rounds.wins.len())
+            .filter(|id| rounds.wins[*id] == best)
+            .collect();
+        let mut lines = vec![match leaders.as_slice() {
+            [winner] => format!("Player {} wins!", winner + 1),
+            _ => "A draw".to_string(),
+        }];
+        lines.push(
+            rounds
+                .wins
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(" - "),
+        );
+        spawn_game_over_menu(&mut commands, &asset_server, &lines);
+        return;
+    }
+    // a daily challenge is kept with the day's other runs instead
+    if let Some(challenge) = daily.0.as_ref() {
+        let score = players.0.first().map_or(0, |player| player.score);
+        if cheated.0 {
+            let lines = [
+                format!("Daily {}: {}", challenge.date(), challenge.describe()),
+                format!("Score: {}", score),
+                CHEATED.to_string(),
+            ];
+            spawn_game_over_menu(&mut commands, &asset_server, &lines);
+            return;
+        }
+        let best = daily::record(challenge, score, level.0, seconds);
+        let lines = [
+            format!("Daily {}: {}", challenge.date(), challenge.describe()),
+            format!("Score: {}", score),
+            format!("Best today: {} ({} tries)", best.score, best.attempts),
+            challenge.share_code(&best),
+        ];
+        spawn_game_over_menu(&mut commands, &asset_server, &lines);
+        return;
+    }
+
+    // what the run was played on, which the settings may not say any more
+    let difficulty = difficulty.0;
+    let mut lines: Vec<String> = match players.0.as_slice() {
+        [player] => vec![format!("Score: {}  ({:?})", player.score, difficulty)],
+        players => players
+            .iter()
+            .enumerate()
+            .map(|(id, player)| format!("Player {}: {}  ({:?})", id + 1, player.score, difficulty))
+            .collect(),
+    };
+    match *mode {
+        GameMode::TimeAttack => lines.push(format!("Waves cleared: {}", level.waves_cleared())),
+        GameMode::Survival => lines.push(format!("Survived {}", clock_text(seconds))),
+        _ => {}
+    }
+    if cheated.0 {
+        lines.push(CHEATED.to_string());
+    }
+    // each player's score goes in the mode's table on its own
+    let Some(table) = mode.score_table().filter(|_| !cheated.0) else {
+        spawn_game_over_menu(&mut commands, &asset_server, &lines);
+        return;
+    };
+    if let Some(index) = GameMode::SCORE_TABLES.iter().position(|t| *t == table) {
+        shown.0 = index;
+    }
+    let mut new_high_score = false;
+    for player in players.0.iter() {
+        new_high_score |= high_scores.submit(
+            table,
+            HighScore {
+                score: player.score,
+                level: level.0,
+                waves_cleared: level.waves_cleared(),
+                seconds,
+                difficulty,
+            },
+        );
+    }
+    if new_high_score {
+        lines.push("New high score!".to_string());
+    }
+    spawn_game_over_menu(&mut commands, &asset_server, &lines);
+}
+
+fn spawn_game_over_menu(commands: &mut Commands, asset_server: &AssetServer, lines: &[String]) {
+    spawn_menu(
+        commands,
+        asset_server,
+        "GAME OVER",
+        lines,
+        &[
+            ("Play Again", MenuAction::Goto(GameState::Playing)),
+            ("High Scores", MenuAction::Goto(GameState::HighScores)),
+            ("Main Menu", MenuAction::Goto(GameState::MainMenu)),
+        ],
+    );
+}
+
+fn remove_menu(mut commands: Commands, query: Query<Entity, With<Menu>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// leave the title screen when the user presses anything
+fn title_input(mut input: MenuInput, mut state: ResMut<State<GameState>>) {
+    if input.any() {
+        state.set(GameState::MainMenu).unwrap();
+        input.clear();
+    }
+}
+
+/// Move the cursor through the items of the active menu and activate them
+fn menu_navigation(
+    mut input: MenuInput,
+    mut cursor: ResMut<MenuCursor>,
+    mut state: ResMut<State<GameState>>,
+    mut mode: ResMut<GameMode>,
+    mut pending: ResMut<PendingRun>,
+    mut exit: EventWriter<AppExit>,
+    items: Query<&MenuItem>,
+) {
+    let count = items.iter().count();
+    if count == 0 {
+        return;
+    }
+
+    if input.up() {
+        cursor.0 = (cursor.0 + count - 1) % count;
+    }
+    if input.down() {
+        cursor.0 = (cursor.0 + 1) % count;
+    }
+
+    let action = if input.confirm() {
+        items
+            .iter()
+            .find(|item| item.index == cursor.0)
+            .map(|item| item.action)
+    } else if input.back() {
+        back_action(*state.current())
+    } else {
+        None
+    };
+
+    if let Some(action) = action {
+        match action {
+            // replace unwinds the pause overlay when quitting from it
+            MenuAction::Goto(next) => state.replace(next).unwrap(),
+            MenuAction::Continue => {
+                // it could have gone since the menu was shown
+                let Some(save) = SaveGame::load() else {
+                    return;
+                };
+                *mode = save.mode;
+                pending.0 = Some(save);
+                state.replace(GameState::Playing).unwrap();
+            }
+            MenuAction::Start(next) => {
+                *mode = next;
+                state.replace(GameState::Playing).unwrap();
+            }
+            MenuAction::Resume => state.pop().unwrap(),
+            MenuAction::Quit => exit.send(AppExit),
+            // settings handle their own input
+            MenuAction::Adjust(_) => return,
+        }
+        input.clear();
+    }
+}
+
+/// where the back button (escape, or B on a gamepad) leads from each menu
+fn back_action(state: GameState) -> Option<MenuAction> {
+    match state {
+        GameState::Paused => Some(MenuAction::Resume),
+        GameState::MainMenu => Some(MenuAction::Goto(GameState::Title)),
+        GameState::ModeSelect
+        | GameState::Settings
+        | GameState::HighScores
+        | GameState::GameOver => Some(MenuAction::Goto(GameState::MainMenu)),
+        GameState::Title | GameState::Playing | GameState::Online => None,
+    }
+}
+
+fn highlight_selected(cursor: Res<MenuCursor>, mut query: Query<(&MenuItem, &mut Text)>) {
+    for (item, mut text) in query.iter_mut() {
+        let color = if item.index == cursor.0 {
+            Color::WHITE
+        } else {
+            Color::GRAY
+        };
+        // avoid touching the text (and re-laying it out) every frame
+        if text.sections[0].style.color == color {
+            continue;
+        }
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
+/// freeze the world while the pause menu is open
+fn pause_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = false;
+}
+
+fn resume_physics(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = true;
+}
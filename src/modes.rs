@@ -0,0 +1,225 @@
+use crate::asteroid::{spawn_into_field, DeferredSpawns};
+use crate::components::*;
+use crate::difficulty::GameDifficulty;
+use crate::events::ShipDestroyed;
+use crate::pool::Pool;
+use crate::resources::{Arena, GameMode, GameRng, GameTime};
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use bevy::utils::Duration;
+
+/// The single player modes that play by rules of their own: time attack, survival
+/// and one life. Each has a resource with its rules, and the game clock they share
+/// is shown on the HUD
+pub struct ModesPlugin;
+
+impl Plugin for ModesPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameClock::default())
+            .insert_resource(TimeAttack::default())
+            .insert_resource(Survival::default())
+            .insert_resource(OneLife::default())
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_clock))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(tick_clock)
+                    .with_system(time_attack_clock.after(tick_clock))
+                    .with_system(stream_asteroids.after(tick_clock)),
+            );
+    }
+}
+
+/// How long the current game has been going, leaving out time spent paused
+#[derive(Resource, Default)]
+pub struct GameClock(pub Stopwatch);
+
+/// Time attack: clear as many waves as possible before the time is up. There are
+/// no lives; a lost ship comes back, but costs time
+#[derive(Resource)]
+pub struct TimeAttack {
+    pub duration: Duration,
+    /// taken off the clock for each ship lost
+    pub penalty: Duration,
+}
+
+impl Default for TimeAttack {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(180),
+            penalty: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TimeAttack {
+    pub fn remaining(&self, clock: &GameClock) -> Duration {
+        self.duration.saturating_sub(clock.0.elapsed())
+    }
+
+    /// a ship was lost, which costs the penalty
+    pub fn penalize(&self, clock: &mut GameClock) {
+        let elapsed = clock.0.elapsed() + self.penalty;
+        clock.0.set_elapsed(elapsed);
+    }
+}
+
+/// Survival: there are no waves, asteroids stream in from the edges one at a time,
+/// more and more often, and never stop
+#[derive(Resource)]
+pub struct Survival {
+    /// seconds between asteroids at the start
+    pub first_interval: f32,
+    /// seconds between asteroids once they come as fast as they get
+    pub shortest_interval: f32,
+    /// seconds it takes to get from the first interval to the shortest
+    pub ramp: f32,
+    /// no more come in while the field has this many, of any size
+    pub max_asteroids: usize,
+    /// until the next asteroid comes in
+    pub next: Timer,
+}
+
+impl Default for Survival {
+    fn default() -> Self {
+        Self {
+            first_interval: 6.0,
+            shortest_interval: 1.5,
+            ramp: 300.0,
+            max_asteroids: 60,
+            next: Timer::from_seconds(6.0, TimerMode::Once),
+        }
+    }
+}
+
+impl Survival {
+    /// the time between asteroids, `seconds` into the game
+    pub fn interval(&self, seconds: f32) -> f32 {
+        let progress = (seconds / self.ramp).min(1.0);
+        self.first_interval + (self.shortest_interval - self.first_interval) * progress
+    }
+}
+
+/// One life: the classic game, with a single ship and nothing to fall back on
+#[derive(Resource)]
+pub struct OneLife {
+    pub lives: u32,
+}
+
+impl Default for OneLife {
+    fn default() -> Self {
+        Self { lives: 1 }
+    }
+}
+
+/// minutes and seconds, for the HUD and the high score tables
+pub fn clock_text(seconds: u32) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn start_clock(mut clock: ResMut<GameClock>, mut survival: ResMut<Survival>) {
+    clock.0.reset();
+    let first = survival.interval(0.0);
+    survival.next = Timer::from_seconds(first, TimerMode::Once);
+}
+
+fn tick_clock(time: GameTime, mut clock: ResMut<GameClock>) {
+    clock.0.tick(time.delta());
+}
+
+/// Take the penalty off for each ship lost, and end the game when the time is up
+fn time_attack_clock(
+    mode: Res<GameMode>,
+    rules: Res<TimeAttack>,
+    mut clock: ResMut<GameClock>,
+    mut state: ResMut<State<GameState>>,
+    mut destroyed_events: EventReader<ShipDestroyed>,
+) {
+    if *mode != GameMode::TimeAttack {
+        return;
+    }
+    for _ in destroyed_events.iter() {
+        rules.penalize(&mut clock);
+    }
+    if rules.remaining(&clock).is_zero() {
+        state.set(GameState::GameOver).unwrap();
+    }
+}
+
+/// Bring in the next asteroid when it is due. Clearing the field still brings a
+/// fresh wave, as it does in the other modes
+#[allow(clippy::too_many_arguments)]
+fn stream_asteroids(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    mode: Res<GameMode>,
+    arena: Res<Arena>,
+    time: GameTime,
+    clock: Res<GameClock>,
+    mut survival: ResMut<Survival>,
+    mut rng: ResMut<GameRng>,
+    mut deferred: ResMut<DeferredSpawns>,
+    ship_query: Query<&Transform, With<Ship>>,
+    asteroid_query: Query<(&Asteroid, &Transform)>,
+) {
+    if *mode != GameMode::Survival {
+        return;
+    }
+    if !survival.next.tick(time.delta()).finished() {
+        return;
+    }
+    let interval = survival.interval(clock.0.elapsed_secs());
+    survival.next = Timer::from_seconds(interval, TimerMode::Once);
+    if asteroid_query.iter().count() + deferred.0.len() >= survival.max_asteroids {
+        return;
+    }
+    spawn_into_field(
+        &mut commands,
+        &mut pool,
+        difficulty.0,
+        &mut rng.rng,
+        &arena,
+        &ship_query,
+        &asteroid_query,
+        &mut deferred,
+        AsteroidSize::Large,
+        1,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survival_speeds_up_to_its_shortest_interval() {
+        let survival = Survival::default();
+        assert_eq!(survival.interval(0.0), survival.first_interval);
+        let halfway = survival.interval(survival.ramp / 2.0);
+        assert!(
+            (halfway - (survival.first_interval + survival.shortest_interval) / 2.0).abs() < 1e-4
+        );
+        assert_eq!(survival.interval(survival.ramp), survival.shortest_interval);
+        // and no shorter, however long it goes on
+        assert_eq!(
+            survival.interval(survival.ramp * 10.0),
+            survival.shortest_interval
+        );
+    }
+
+    #[test]
+    fn a_lost_ship_costs_time_attack_time() {
+        let rules = TimeAttack::default();
+        let mut clock = GameClock::default();
+        clock.0.set_elapsed(Duration::from_secs(60));
+        rules.penalize(&mut clock);
+        assert_eq!(
+            rules.remaining(&clock),
+            rules.duration - Duration::from_secs(60) - rules.penalty
+        );
+        // the clock can't go below zero
+        clock.0.set_elapsed(rules.duration);
+        rules.penalize(&mut clock);
+        assert!(rules.remaining(&clock).is_zero());
+    }
+}
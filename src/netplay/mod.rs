@@ -0,0 +1,497 @@
+mod session;
+mod sim;
+mod transport;
+
+use crate::components::*;
+use crate::menu::MenuInput;
+use crate::resources::GameMode;
+use crate::settings::{launch_flag, launch_value, Difficulty, Settings};
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use crate::ship::SHIP_POINTS;
+use crate::ufo::SAUCER_POINTS;
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashMap, HashSet};
+use bevy_prototype_lyon::prelude::*;
+use rand::Rng;
+use session::RollbackSession;
+use sim::{Rules, SimState, TICK};
+use transport::{Clock, Conditions, LoopbackTransport, Packet, Transport};
+
+/// how often the host says hello until someone answers
+const HELLO_INTERVAL: f32 = 0.5;
+/// steps taken in one frame at most, so a long hitch doesn't turn into a longer one
+const MAX_STEPS_PER_FRAME: u32 = 4;
+/// how late and how often lost the packets of `--loopback` are
+const LOOPBACK_LATENCY: Duration = Duration::from_millis(60);
+const LOOPBACK_JITTER: Duration = Duration::from_millis(30);
+const LOOPBACK_LOSS: f32 = 0.05;
+
+/// Online two-player games with rollback netcode, started from the command line:
+///
+/// - `--host 7000` waits for someone to join on UDP port 7000
+/// - `--join 192.168.1.20:7000` joins them
+/// - `--loopback` plays against a bot through a pretend network with latency, jitter
+///   and packet loss, all in the one process. Both sides are simulated, so it shows
+///   the rollbacks and would show a desync
+///
+/// Add `--versus` (on the host) to shoot at each other instead of the rocks. The
+/// host's difficulty setting goes for both sides. The game runs in its own `Online`
+/// state on a deterministic simulation of the rules (see `SimState`), drawn with the
+/// usual shapes; escape leaves it
+///
+/// Both players need the same build of the game for the same target, or the
+/// simulation can drift apart (a desync, which the HUD reports). There is no online
+/// play in the browser build yet
+pub struct NetplayPlugin;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        let difficulty = app
+            .world
+            .get_resource::<Settings>()
+            .map_or_else(Difficulty::default, |settings| settings.gameplay.difficulty);
+        let Some(netplay) = Netplay::from_launch_args(difficulty) else {
+            return;
+        };
+        app.insert_resource(netplay)
+            .insert_resource(Views::default())
+            .add_system_set(SystemSet::on_update(GameState::Title).with_system(go_online))
+            .add_system_set(SystemSet::on_enter(GameState::Online).with_system(spawn_status))
+            .add_system_set(
+                SystemSet::on_update(GameState::Online)
+                    .with_system(handshake)
+                    .with_system(run_sessions.after(handshake))
+                    .with_system(draw_state.after(run_sessions))
+                    .with_system(update_status.after(run_sessions))
+                    .with_system(leave),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Online).with_system(remove_views));
+    }
+}
+
+/// The other side hasn't answered yet
+struct Handshake {
+    transport: Box<dyn Transport>,
+    /// the host picks the rules; whoever joins gets them in the hello
+    hello: Option<Rules>,
+    resend: Timer,
+    /// for the status line
+    waiting_for: String,
+}
+
+#[derive(Resource)]
+struct Netplay {
+    handshake: Option<Handshake>,
+    session: Option<RollbackSession>,
+    /// in loopback, the other player's side of the game, flown by a bot
+    peer: Option<RollbackSession>,
+    /// in loopback, the pretend network's time, moved on a step at a time
+    clock: Clock,
+    /// time not yet simulated
+    accumulator: f32,
+    /// set once the game has been left, so the title doesn't start another
+    finished: bool,
+}
+
+impl Netplay {
+    fn from_launch_args(difficulty: Difficulty) -> Option<Self> {
+        let rules = Rules {
+            seed: rand::thread_rng().gen(),
+            versus: launch_flag("versus"),
+            difficulty,
+        };
+        let mut netplay = Self {
+            handshake: None,
+            session: None,
+            peer: None,
+            clock: Clock::default(),
+            accumulator: 0.0,
+            finished: false,
+        };
+
+        if launch_flag("loopback") {
+            let (a, b) = LoopbackTransport::pair(Conditions {
+                latency: LOOPBACK_LATENCY,
+                jitter: LOOPBACK_JITTER,
+                loss: LOOPBACK_LOSS,
+                seed: rand::thread_rng().gen(),
+                clock: netplay.clock.clone(),
+            });
+            netplay.session = Some(RollbackSession::new(
+                0,
+                SimState::new(rules, 2),
+                Box::new(a),
+            ));
+            netplay.peer = Some(RollbackSession::new(
+                1,
+                SimState::new(rules, 2),
+                Box::new(b),
+            ));
+            return Some(netplay);
+        }
+
+        let (transport, hello, waiting_for) = connect(rules)?;
+        netplay.handshake = Some(Handshake {
+            transport,
+            hello,
+            resend: Timer::from_seconds(HELLO_INTERVAL, TimerMode::Repeating),
+            waiting_for,
+        });
+        Some(netplay)
+    }
+}
+
+/// the transport, the host's hello and what the status line says is awaited
+type Connection = (Box<dyn Transport>, Option<Rules>, String);
+
+#[cfg(not(target_arch = "wasm32"))]
+fn connect(rules: Rules) -> Option<Connection> {
+    use transport::UdpTransport;
+
+    let (transport, hello, waiting_for) = if let Some(port) = launch_value("host") {
+        let port: u16 = port.parse().ok()?;
+        let transport = UdpTransport::host(port);
+        (
+            transport,
+            Some(rules),
+            format!("someone to join on port {}", port),
+        )
+    } else {
+        let address = launch_value("join")?;
+        let transport = UdpTransport::join(&address);
+        (transport, None, format!("the host at {}", address))
+    };
+    match transport {
+        Ok(transport) => Some((Box::new(transport), hello, waiting_for)),
+        Err(error) => {
+            error!("could not open a UDP socket: {}", error);
+            None
+        }
+    }
+}
+
+/// there is no UDP in the browser
+#[cfg(target_arch = "wasm32")]
+fn connect(_rules: Rules) -> Option<Connection> {
+    if launch_value("host").is_some() || launch_value("join").is_some() {
+        warn!("online play in the browser needs a WebRTC transport");
+    }
+    None
+}
+
+fn go_online(netplay: Res<Netplay>, mut state: ResMut<State<GameState>>) {
+    if !netplay.finished {
+        state.set(GameState::Online).unwrap();
+    }
+}
+
+/// Say hello until the other side says it back, then start the game with the same
+/// seed and rules on both sides
+fn handshake(time: Res<Time>, mut netplay: ResMut<Netplay>) {
+    let Some(handshake) = netplay.handshake.as_mut() else {
+        return;
+    };
+    let packets = handshake.transport.receive();
+    let started = match handshake.hello {
+        // the host: anyone answering has joined. Once they have started they send
+        // inputs, which will do if their hello back was lost
+        Some(rules) => {
+            if handshake.resend.tick(time.delta()).just_finished() {
+                handshake.transport.send(&Packet::Hello(rules));
+            }
+            (!packets.is_empty()).then_some((0, rules))
+        }
+        // joining: answer the host's hello with the same
+        None => packets.iter().find_map(|packet| match packet {
+            Packet::Hello(rules) => {
+                handshake.transport.send(packet);
+                Some((1, *rules))
+            }
+            _ => None,
+        }),
+    };
+
+    if let Some((local, rules)) = started {
+        let handshake = netplay.handshake.take().unwrap();
+        info!("connected, playing as player {}", local + 1);
+        netplay.session = Some(RollbackSession::new(
+            local,
+            SimState::new(rules, 2),
+            handshake.transport,
+        ));
+    }
+}
+
+/// step the game at a fixed rate, however fast the frames come
+fn run_sessions(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    settings: Res<Settings>,
+    mut netplay: ResMut<Netplay>,
+) {
+    let netplay = &mut *netplay;
+    let Some(session) = netplay.session.as_mut() else {
+        return;
+    };
+    let keys = settings.gameplay.controls.keys();
+    let input = ShipInput {
+        left: keyboard_input.pressed(keys.left),
+        right: keyboard_input.pressed(keys.right),
+        thrust: keyboard_input.pressed(keys.thrust),
+        fire: keyboard_input.pressed(keys.fire),
+    };
+
+    netplay.accumulator =
+        (netplay.accumulator + time.delta_seconds()).min(TICK * MAX_STEPS_PER_FRAME as f32);
+    while netplay.accumulator >= TICK {
+        netplay.accumulator -= TICK;
+        netplay.clock.advance(Duration::from_secs_f32(TICK));
+        session.add_local_input(input);
+        session.advance();
+        if let Some(peer) = netplay.peer.as_mut() {
+            let frame = peer.state().frame;
+            peer.add_local_input(bot_input(frame));
+            peer.advance();
+        }
+    }
+}
+
+/// The loopback opponent: turns, thrusts and fires in a pattern that changes often
+/// enough to keep the other side guessing wrong
+fn bot_input(frame: u32) -> ShipInput {
+    let phase = frame / 20;
+    ShipInput {
+        left: phase % 5 == 1,
+        right: phase % 7 == 3,
+        thrust: phase.is_multiple_of(3) && frame % 60 < 30,
+        fire: frame % 17 < 3,
+    }
+}
+
+/// something in the simulation that is drawn
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Object {
+    Ship(usize),
+    Asteroid(u32),
+    Laser(u32),
+    Ufo,
+}
+
+/// the entity drawing each object
+#[derive(Resource, Default)]
+struct Views(HashMap<Object, Entity>);
+
+/// anything spawned to show the online game
+#[derive(Component)]
+struct NetplayView;
+
+/// the status line at the top of the screen
+#[derive(Component)]
+struct NetplayStatus;
+
+/// Keep one entity for everything in the local session's game, where it is now
+fn draw_state(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    shapes: Res<ShapeCache>,
+    netplay: Res<Netplay>,
+    mut views: ResMut<Views>,
+    mut transforms: Query<(&mut Transform, &mut Visibility), With<NetplayView>>,
+) {
+    let Some(session) = netplay.session.as_ref() else {
+        return;
+    };
+    let state = session.state();
+    let objects = state
+        .ships
+        .iter()
+        .enumerate()
+        .map(|(id, ship)| (Object::Ship(id), ship.position, ship.angle, ship.alive))
+        .chain(state.asteroids.iter().map(|asteroid| {
+            (
+                Object::Asteroid(asteroid.id),
+                asteroid.position,
+                asteroid.angle,
+                true,
+            )
+        }))
+        .chain(
+            state
+                .lasers
+                .iter()
+                .chain(state.bullets.iter())
+                .map(|laser| (Object::Laser(laser.id), laser.position, laser.angle, true)),
+        )
+        .chain(
+            state
+                .ufo
+                .iter()
+                .map(|saucer| (Object::Ufo, saucer.position, 0.0, true)),
+        );
+
+    let mut seen = HashSet::new();
+    for (object, position, angle, visible) in objects {
+        seen.insert(object);
+        let transform = Transform {
+            translation: position.extend(0.0),
+            rotation: Quat::from_rotation_z(angle),
+            ..default()
+        };
+        if let Some((mut current, mut visibility)) = views
+            .0
+            .get(&object)
+            .and_then(|entity| transforms.get_mut(*entity).ok())
+        {
+            *current = transform;
+            if visibility.is_visible != visible {
+                visibility.is_visible = visible;
+            }
+            continue;
+        }
+
+        let mut entity = match object {
+            Object::Ship(id) => commands.spawn(GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: SHIP_POINTS.to_vec(),
+                    closed: true,
+                },
+                settings.ship_draw_mode(Player(id)),
+                transform,
+            )),
+            Object::Asteroid(id) => {
+                let size = state
+                    .asteroids
+                    .iter()
+                    .find(|asteroid| asteroid.id == id)
+                    .map_or(AsteroidSize::Large, |asteroid| asteroid.size);
+                commands.spawn(shapes.bundle(ShapeKind::Asteroid(size), transform))
+            }
+            Object::Laser(id) => {
+                let kind = if state.bullets.iter().any(|bullet| bullet.id == id) {
+                    ShapeKind::Bullet
+                } else {
+                    ShapeKind::Laser
+                };
+                commands.spawn(shapes.bundle(kind, transform))
+            }
+            Object::Ufo => commands.spawn(GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: SAUCER_POINTS.to_vec(),
+                    closed: true,
+                },
+                settings.draw_mode(),
+                transform,
+            )),
+        };
+        views.0.insert(object, entity.insert(NetplayView).id());
+    }
+
+    views.0.retain(|object, entity| {
+        let keep = seen.contains(object);
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+}
+
+fn spawn_status(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 24.0,
+                    color: Color::WHITE,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(20.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(NetplayView)
+        .insert(NetplayStatus);
+}
+
+/// the scores, and how the connection is doing
+fn update_status(netplay: Res<Netplay>, mut query: Query<&mut Text, With<NetplayStatus>>) {
+    let value = match (&netplay.handshake, &netplay.session) {
+        (Some(handshake), _) => format!("Waiting for {}...", handshake.waiting_for),
+        (None, Some(session)) => {
+            let state = session.state();
+            let versus = state.mode == GameMode::Versus;
+            let scores: Vec<String> = state
+                .players
+                .0
+                .iter()
+                .enumerate()
+                .map(|(id, player)| {
+                    let you = if id == session.local() { " (you)" } else { "" };
+                    // lives in co-op, rounds won in versus
+                    let extra = if versus {
+                        state.rounds.wins[id]
+                    } else {
+                        player.lives
+                    };
+                    format!("P{}{} {} ({})", id + 1, you, player.score, extra)
+                })
+                .collect();
+            let progress = if versus {
+                format!("Round {}", state.rounds.number)
+            } else {
+                format!("Level {}", state.level)
+            };
+            let stats = session.stats;
+            let mut value = format!(
+                "{}    {}\nFrame {}  rollbacks {} ({} frames)  stalls {}",
+                scores.join("    "),
+                progress,
+                state.frame,
+                stats.rollbacks,
+                stats.resimulated,
+                stats.stalls
+            );
+            if state.over {
+                value.push_str("\nGame over");
+            }
+            if let Some(frame) = stats.desync {
+                value.push_str(&format!("\nDESYNC at frame {}", frame));
+            }
+            value
+        }
+        (None, None) => String::new(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+/// escape goes back to the menu, and that is the end of the online game
+fn leave(mut netplay: ResMut<Netplay>, mut state: ResMut<State<GameState>>, mut input: MenuInput) {
+    if input.pause() || input.back() {
+        netplay.finished = true;
+        state.set(GameState::MainMenu).unwrap();
+        input.clear();
+    }
+}
+
+fn remove_views(
+    mut commands: Commands,
+    mut views: ResMut<Views>,
+    query: Query<Entity, With<NetplayView>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    views.0.clear();
+}
@@ -0,0 +1,463 @@
+use super::sim::SimState;
+use super::transport::{Packet, Transport};
+use crate::components::ShipInput;
+use bevy::prelude::*;
+use std::collections::BTreeMap;
+
+/// how many frames the session may run ahead of the other side's inputs on
+/// predictions. Past that it waits for them
+pub const MAX_PREDICTION: u32 = 8;
+/// Local inputs are played this many frames after they are pressed. That much of
+/// the latency is hidden without any rolling back
+pub const INPUT_DELAY: u32 = 2;
+/// frames between comparing checksums with the other side
+const CHECKSUM_INTERVAL: u32 = 30;
+/// the most inputs sent in one packet; a peer that far behind catches up over
+/// several
+const MAX_INPUTS_PER_PACKET: u32 = 64;
+/// enough to roll back to any frame a prediction was made for
+const SNAPSHOTS: usize = MAX_PREDICTION as usize + 2;
+
+/// what happened to a session so far, for the HUD
+#[derive(Default, Clone, Copy)]
+pub struct SessionStats {
+    pub rollbacks: u32,
+    /// frames simulated again after rolling back
+    pub resimulated: u32,
+    /// frames spent waiting for the other side
+    pub stalls: u32,
+    /// the first frame the two sides disagreed on
+    pub desync: Option<u32>,
+}
+
+/// GGPO style rollback for a game between two players. The game steps on with
+/// the local player's inputs and a guess for the remote player's (whatever they
+/// were last pressing). When their real inputs arrive and the guess was wrong, the
+/// game goes back to the snapshot before it and is simulated again up to now
+pub struct RollbackSession {
+    local: usize,
+    remote: usize,
+    state: SimState,
+    /// the state at the start of each recent frame, by frame modulo their count
+    snapshots: Vec<Option<SimState>>,
+    /// each player's inputs that are known, by frame
+    inputs: [BTreeMap<u32, ShipInput>; 2],
+    /// the guesses used for the remote player's frames that haven't arrived
+    predictions: BTreeMap<u32, ShipInput>,
+    /// the first frame of the remote player's inputs we don't have yet
+    remote_received: u32,
+    /// the first frame of our inputs the remote player doesn't have yet
+    remote_ack: u32,
+    next_checksum: u32,
+    local_checksums: BTreeMap<u32, u64>,
+    remote_checksums: BTreeMap<u32, u64>,
+    transport: Box<dyn Transport>,
+    pub stats: SessionStats,
+}
+
+impl RollbackSession {
+    /// `local` is 0 for the host and 1 for whoever joined
+    pub fn new(local: usize, state: SimState, transport: Box<dyn Transport>) -> Self {
+        // nothing is pressed during the first frames, until the delayed inputs start
+        let delayed = || {
+            (0..INPUT_DELAY)
+                .map(|frame| (frame, ShipInput::default()))
+                .collect()
+        };
+        Self {
+            local,
+            remote: 1 - local,
+            state,
+            snapshots: vec![None; SNAPSHOTS],
+            inputs: [delayed(), delayed()],
+            predictions: BTreeMap::new(),
+            remote_received: INPUT_DELAY,
+            remote_ack: 0,
+            next_checksum: CHECKSUM_INTERVAL,
+            local_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            transport,
+            stats: SessionStats::default(),
+        }
+    }
+
+    /// the game as it is predicted to be now
+    pub fn state(&self) -> &SimState {
+        &self.state
+    }
+
+    pub fn local(&self) -> usize {
+        self.local
+    }
+
+    /// Play `input` for the local player `INPUT_DELAY` frames from now. While the
+    /// session is waiting for the other side, the frame doesn't move on and the
+    /// input already given for it stays
+    pub fn add_local_input(&mut self, input: ShipInput) {
+        let frame = self.state.frame + INPUT_DELAY;
+        self.inputs[self.local].entry(frame).or_insert(input);
+    }
+
+    /// Take in what the other side sent, roll back if a guess was wrong and take one
+    /// step. Returns false when it couldn't step because the other side is too far
+    /// behind
+    pub fn advance(&mut self) -> bool {
+        if let Some(frame) = self.receive() {
+            self.rollback(frame);
+        }
+        self.compare_checksums();
+
+        let stepped = self.state.frame < self.remote_received + MAX_PREDICTION;
+        if stepped {
+            self.save_and_step();
+        } else {
+            self.stats.stalls += 1;
+        }
+        self.send_inputs();
+        self.send_checksums();
+        self.prune();
+        stepped
+    }
+
+    /// handle the packets that came in, returning the earliest frame that was
+    /// guessed wrong
+    fn receive(&mut self) -> Option<u32> {
+        let mut mispredicted: Option<u32> = None;
+        for packet in self.transport.receive() {
+            match packet {
+                Packet::Input { ack, start, inputs } => {
+                    // they can't have more of ours than there are
+                    let sent = self.inputs[self.local]
+                        .last_key_value()
+                        .map_or(0, |(&newest, _)| newest + 1);
+                    self.remote_ack = self.remote_ack.max(ack.min(sent));
+                    // Nothing they send can be further ahead than this. Resent
+                    // packets start before `remote_received` and the frames we
+                    // already have are skipped
+                    let newest = self.newest_remote_frame();
+                    if start > newest {
+                        continue;
+                    }
+                    for (frame, input) in (start..=newest).zip(inputs) {
+                        if frame < self.remote_received
+                            || self.inputs[self.remote].contains_key(&frame)
+                        {
+                            continue;
+                        }
+                        self.inputs[self.remote].insert(frame, input);
+                        if self
+                            .predictions
+                            .remove(&frame)
+                            .is_some_and(|guess| guess != input)
+                        {
+                            mispredicted =
+                                Some(mispredicted.map_or(frame, |earliest| earliest.min(frame)));
+                        }
+                    }
+                    while self.inputs[self.remote].contains_key(&self.remote_received) {
+                        self.remote_received += 1;
+                    }
+                }
+                Packet::Checksum { frame, value } => {
+                    if frame <= self.newest_remote_frame() {
+                        self.remote_checksums.insert(frame, value);
+                    }
+                }
+                // the other side hasn't heard that we started yet
+                Packet::Hello(_) => {}
+            }
+        }
+        mispredicted
+    }
+
+    /// The furthest frame the other side can be playing inputs for: it only runs
+    /// `MAX_PREDICTION` frames past ours, and its inputs are delayed on top of that
+    fn newest_remote_frame(&self) -> u32 {
+        self.state.frame + MAX_PREDICTION + INPUT_DELAY
+    }
+
+    /// go back to the start of `frame` and simulate up to the current frame again,
+    /// with the inputs that are known now
+    fn rollback(&mut self, frame: u32) {
+        let current = self.state.frame;
+        let Some(snapshot) = self.snapshot(frame).cloned() else {
+            warn!("no snapshot left to roll back to frame {}", frame);
+            return;
+        };
+        self.state = snapshot;
+        while self.state.frame < current {
+            self.save_and_step();
+        }
+        self.stats.rollbacks += 1;
+        self.stats.resimulated += current - frame;
+    }
+
+    fn snapshot(&self, frame: u32) -> Option<&SimState> {
+        self.snapshots[frame as usize % SNAPSHOTS]
+            .as_ref()
+            .filter(|state| state.frame == frame)
+    }
+
+    fn save_and_step(&mut self) {
+        let frame = self.state.frame;
+        self.snapshots[frame as usize % SNAPSHOTS] = Some(self.state.clone());
+        let mut inputs = [ShipInput::default(); 2];
+        inputs[self.local] = self.inputs[self.local]
+            .get(&frame)
+            .copied()
+            .unwrap_or_default();
+        inputs[self.remote] = match self.inputs[self.remote].get(&frame) {
+            Some(input) => *input,
+            None => {
+                // whatever they were last known to be pressing
+                let guess = self.inputs[self.remote]
+                    .range(..frame)
+                    .next_back()
+                    .map(|(_, input)| *input)
+                    .unwrap_or_default();
+                self.predictions.insert(frame, guess);
+                guess
+            }
+        };
+        self.state.step(&inputs);
+    }
+
+    fn send_inputs(&mut self) {
+        let Some((&newest, _)) = self.inputs[self.local].last_key_value() else {
+            return;
+        };
+        let start = self
+            .remote_ack
+            .max(newest.saturating_sub(MAX_INPUTS_PER_PACKET - 1));
+        let inputs = self.inputs[self.local]
+            .range(start..)
+            .take(MAX_INPUTS_PER_PACKET as usize)
+            .map(|(_, input)| *input)
+            .collect();
+        self.transport.send(&Packet::Input {
+            ack: self.remote_received,
+            start,
+            inputs,
+        });
+    }
+
+    /// A frame is settled once both players' inputs before it are known; it can't
+    /// change after that. This is the latest one
+    fn settled(&self) -> u32 {
+        self.remote_received.min(self.state.frame)
+    }
+
+    /// the checksum of the game at the start of `frame`, if it is still around
+    fn checksum(&self, frame: u32) -> Option<u64> {
+        let state = if frame == self.state.frame {
+            Some(&self.state)
+        } else {
+            self.snapshot(frame)
+        };
+        state.map(SimState::checksum)
+    }
+
+    /// only settled frames are compared
+    fn send_checksums(&mut self) {
+        while self.next_checksum <= self.settled() {
+            let frame = self.next_checksum;
+            if let Some(value) = self.checksum(frame) {
+                self.local_checksums.insert(frame, value);
+                self.transport.send(&Packet::Checksum { frame, value });
+            }
+            self.next_checksum += CHECKSUM_INTERVAL;
+        }
+    }
+
+    fn compare_checksums(&mut self) {
+        let frames: Vec<u32> = self
+            .local_checksums
+            .keys()
+            .filter(|frame| self.remote_checksums.contains_key(frame))
+            .copied()
+            .collect();
+        for frame in frames {
+            let ours = self.local_checksums.remove(&frame);
+            let theirs = self.remote_checksums.remove(&frame);
+            if ours != theirs && self.stats.desync.is_none() {
+                error!("desync: the two sides disagree about frame {}", frame);
+                self.stats.desync = Some(frame);
+            }
+        }
+    }
+
+    /// forget what can't be needed again
+    fn prune(&mut self) {
+        let oldest = self.state.frame.saturating_sub(SNAPSHOTS as u32);
+        // our inputs are kept until the other side has them
+        let local = &mut self.inputs[self.local];
+        *local = local.split_off(&oldest.min(self.remote_ack));
+        // theirs, keeping the last one for guessing with
+        let remote = &mut self.inputs[self.remote];
+        let keep = oldest.min(self.remote_received.saturating_sub(1));
+        *remote = remote.split_off(&keep);
+        self.predictions = self.predictions.split_off(&oldest);
+        // checksums the other side never sent (or we never got) are given up on
+        let stale = self.state.frame.saturating_sub(CHECKSUM_INTERVAL * 10);
+        self.local_checksums = self.local_checksums.split_off(&stale);
+        self.remote_checksums = self.remote_checksums.split_off(&stale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::bot_input;
+    use super::super::sim::{Rules, TICK};
+    use super::super::transport::{Clock, Conditions, LoopbackTransport};
+    use super::*;
+    use crate::settings::Difficulty;
+    use bevy::utils::Duration;
+
+    /// long enough for plenty of late and lost packets, and for some checksums
+    const FRAMES: u32 = 600;
+
+    /// two sides of a game through a network that loses and reorders packets,
+    /// and the clock they both go by
+    fn pair(versus: bool) -> (RollbackSession, RollbackSession, Clock) {
+        let clock = Clock::default();
+        let (a, b) = LoopbackTransport::pair(Conditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(40),
+            loss: 0.1,
+            seed: 7,
+            clock: clock.clone(),
+        });
+        let rules = Rules {
+            seed: 1,
+            versus,
+            difficulty: Difficulty::Hard,
+        };
+        (
+            RollbackSession::new(0, SimState::new(rules, 2), Box::new(a)),
+            RollbackSession::new(1, SimState::new(rules, 2), Box::new(b)),
+            clock,
+        )
+    }
+
+    /// Play `frames` steps on both sides with their own bots flying, keeping the
+    /// checksum of every frame either side has settled while its snapshot is around
+    fn play(
+        sessions: &mut [RollbackSession; 2],
+        clock: &Clock,
+        frames: u32,
+        settled: &mut [BTreeMap<u32, u64>; 2],
+    ) {
+        for _ in 0..frames {
+            clock.advance(Duration::from_secs_f32(TICK));
+            for (side, session) in sessions.iter_mut().enumerate() {
+                // the two bots are out of step, so the guesses go wrong
+                let frame = session.state().frame + side as u32 * 37;
+                session.add_local_input(bot_input(frame));
+                session.advance();
+                let latest = session.settled();
+                for frame in latest.saturating_sub(SNAPSHOTS as u32)..=latest {
+                    if let Some(value) = session.checksum(frame) {
+                        settled[side].entry(frame).or_insert(value);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn both_sides_agree_through_a_bad_network() {
+        for versus in [false, true] {
+            let (a, b, clock) = pair(versus);
+            let mut sessions = [a, b];
+            let mut settled = [BTreeMap::new(), BTreeMap::new()];
+            play(&mut sessions, &clock, FRAMES, &mut settled);
+
+            for session in sessions.iter() {
+                assert!(session.stats.rollbacks > 0);
+                assert_eq!(session.stats.desync, None);
+                assert!(session.state().frame > FRAMES / 2);
+            }
+            let mut compared = 0;
+            for (frame, value) in settled[0].iter() {
+                if let Some(other) = settled[1].get(frame) {
+                    assert_eq!(value, other, "the sides differ at frame {}", frame);
+                    compared += 1;
+                }
+            }
+            assert!(compared > FRAMES / 2, "only {} frames compared", compared);
+        }
+    }
+
+    #[test]
+    fn a_desync_is_noticed() {
+        let (a, b, clock) = pair(false);
+        let mut sessions = [a, b];
+        let mut settled = [BTreeMap::new(), BTreeMap::new()];
+        play(&mut sessions, &clock, FRAMES / 4, &mut settled);
+        assert!(sessions
+            .iter()
+            .all(|session| session.stats.desync.is_none()));
+
+        // one side's game goes its own way, in every frame it could roll back to
+        let drifting = &mut sessions[1];
+        drifting.state.players.0[0].score += 1;
+        for snapshot in drifting.snapshots.iter_mut().flatten() {
+            snapshot.players.0[0].score += 1;
+        }
+        play(&mut sessions, &clock, FRAMES / 4, &mut settled);
+        assert!(sessions
+            .iter()
+            .all(|session| session.stats.desync.is_some()));
+    }
+
+    #[test]
+    fn hostile_packets_are_ignored() {
+        let (local, mut other) = LoopbackTransport::pair(Conditions {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            seed: 7,
+            clock: Clock::default(),
+        });
+        let rules = Rules {
+            seed: 1,
+            versus: true,
+            difficulty: Difficulty::Hard,
+        };
+        let mut session = RollbackSession::new(0, SimState::new(rules, 2), Box::new(local));
+        let input = ShipInput {
+            thrust: true,
+            ..default()
+        };
+        for start in [u32::MAX - 1, u32::MAX, 1000] {
+            other.send(&Packet::Input {
+                ack: u32::MAX,
+                start,
+                inputs: vec![input; 8],
+            });
+        }
+        // one that starts in reach, but goes on far past it
+        other.send(&Packet::Input {
+            ack: 0,
+            start: INPUT_DELAY,
+            inputs: vec![input; 64],
+        });
+        other.send(&Packet::Checksum {
+            frame: u32::MAX,
+            value: 0,
+        });
+        session.add_local_input(ShipInput::default());
+        session.advance();
+
+        let newest = MAX_PREDICTION + INPUT_DELAY;
+        assert_eq!(
+            session.inputs[session.remote]
+                .last_key_value()
+                .map(|(&frame, _)| frame),
+            Some(newest)
+        );
+        assert_eq!(session.remote_received, newest + 1);
+        assert_eq!(session.remote_ack, INPUT_DELAY + 1);
+        assert!(session.remote_checksums.is_empty());
+    }
+}
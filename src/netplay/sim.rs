@@ -0,0 +1,622 @@
+use crate::asteroid::{
+    asteroid_collider, asteroid_velocity, fragment, wave_size, SpawnArea, ASTEROID_SPIN, FRAGMENTS,
+};
+use crate::collision::{contact, ordered, touching, Body, Contact, Side};
+use crate::components::{AsteroidSize, FireTimer, Player, ShipInput, Ufo};
+use crate::daily::DailyRun;
+use crate::resources::{Arena, GameMode, Players, Rounds};
+use crate::score::{award, points, UFO_POINTS};
+use crate::settings::Difficulty;
+use crate::ship::{
+    heading, laser_collider, respawn_clear, rotation, ship_collider, ship_mass, start_position,
+    LASER_MARGIN, LASER_SPEED, SHIP_HEIGHT, TURN_SPEED,
+};
+use crate::ufo::{
+    aim, bullet_collider, entry, muzzle, offscreen, saucer_collider, spawn_interval, steer,
+    ENEMY_LASER_SPEED,
+};
+use crate::versus::{count_kill, Kill};
+use crate::wrap::{nearest_image, wrap_position};
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashSet};
+use bevy_rapier2d::prelude::Collider;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// the length of one step. Both sides step at exactly this rate, whatever their
+/// frame rate
+pub const TICK: f32 = 1.0 / 60.0;
+
+/// What the two sides have to agree on to play the same game. The host picks it and
+/// sends it in its hello
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules {
+    pub seed: u64,
+    /// lasers hit the other ship and kills are the score, instead of asteroids
+    pub versus: bool,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Clone)]
+pub struct SimShip {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// radians, counterclockwise from pointing up
+    pub angle: f32,
+    pub alive: bool,
+    fire_timer: FireTimer,
+}
+
+#[derive(Clone)]
+pub struct SimAsteroid {
+    /// stays the same for as long as the asteroid is around, so it can be drawn
+    /// by the same entity
+    pub id: u32,
+    pub size: AsteroidSize,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub angle: f32,
+}
+
+/// a player's laser, or (without an owner) one of the UFO's
+#[derive(Clone)]
+pub struct SimLaser {
+    pub id: u32,
+    pub owner: Option<usize>,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub angle: f32,
+}
+
+#[derive(Clone)]
+pub struct SimUfo {
+    /// its timers
+    ufo: Ufo,
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+/// what each kind of thing collides as, made once
+#[derive(Clone)]
+struct Colliders {
+    ship: Collider,
+    laser: Collider,
+    bullet: Collider,
+    saucer: Collider,
+    asteroids: [Collider; 3],
+}
+
+impl Colliders {
+    fn new() -> Self {
+        Self {
+            ship: ship_collider(),
+            laser: laser_collider(),
+            bullet: bullet_collider(),
+            saucer: saucer_collider(),
+            asteroids: [
+                AsteroidSize::Small,
+                AsteroidSize::Medium,
+                AsteroidSize::Large,
+            ]
+            .map(asteroid_collider),
+        }
+    }
+
+    fn asteroid(&self, size: AsteroidSize) -> &Collider {
+        match size {
+            AsteroidSize::Small => &self.asteroids[0],
+            AsteroidSize::Medium => &self.asteroids[1],
+            AsteroidSize::Large => &self.asteroids[2],
+        }
+    }
+}
+
+/// something that can be hit, by where it is kept
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Thing {
+    Ship(usize),
+    Asteroid(usize),
+    Laser(usize),
+    Bullet(usize),
+    Ufo,
+}
+
+/// The whole of an online game, apart from the drawing. Stepping it with the same
+/// inputs gives the same result on both sides, so only the inputs have to be sent,
+/// and it can be cloned to keep a snapshot to roll back to.
+///
+/// It plays by the game's own rules, calling the same functions as the systems do,
+/// but moves things itself rather than through rapier, which keeps state of its own
+/// that would have to be rolled back too. Things touch when their colliders overlap,
+/// as rapier would find, and it has its own seeded random numbers
+///
+/// It is only the same on both sides when they run the same build for the same
+/// target. The float maths has to round the same way throughout, and the overlap
+/// tests are parry's. Directions go through `heading`, whose trig is libm's, but
+/// parry turns colliders with the platform's
+#[derive(Clone)]
+pub struct SimState {
+    /// how many steps have been taken
+    pub frame: u32,
+    pub level: u32,
+    /// co-op or versus
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+    pub players: Players,
+    pub rounds: Rounds,
+    /// nobody has a ship left, or someone won the match
+    pub over: bool,
+    pub ships: Vec<SimShip>,
+    pub asteroids: Vec<SimAsteroid>,
+    pub lasers: Vec<SimLaser>,
+    pub ufo: Option<SimUfo>,
+    /// the UFO's lasers
+    pub bullets: Vec<SimLaser>,
+    /// asteroids there was no room for yet
+    deferred: Vec<AsteroidSize>,
+    ufo_timer: Timer,
+    rng: StdRng,
+    next_id: u32,
+    half_extents: Vec2,
+    colliders: Colliders,
+}
+
+impl SimState {
+    pub fn new(rules: Rules, players: usize) -> Self {
+        let preset = rules.difficulty.preset();
+        let mode = if rules.versus {
+            GameMode::Versus
+        } else {
+            GameMode::Coop
+        };
+        let mut state = Self {
+            frame: 0,
+            level: 1,
+            mode,
+            difficulty: rules.difficulty,
+            players: Players::new(players, preset.starting_lives),
+            rounds: Rounds::new(players),
+            over: false,
+            ships: Vec::new(),
+            asteroids: Vec::new(),
+            lasers: Vec::new(),
+            ufo: None,
+            bullets: Vec::new(),
+            deferred: Vec::new(),
+            ufo_timer: Timer::from_seconds(spawn_interval(preset.ufo_aggression), TimerMode::Once),
+            rng: StdRng::seed_from_u64(rules.seed),
+            next_id: 0,
+            half_extents: Arena::default().half_extents(),
+            colliders: Colliders::new(),
+        };
+        state.ships = (0..players)
+            .map(|id| state.new_ship(start_position(Player(id), players)))
+            .collect();
+        state.spawn_wave();
+        state
+    }
+
+    fn new_ship(&self, position: Vec2) -> SimShip {
+        SimShip {
+            position,
+            velocity: Vec2::ZERO,
+            angle: 0.0,
+            alive: true,
+            fire_timer: FireTimer::ready(self.difficulty.preset().fire_cooldown),
+        }
+    }
+
+    /// Move everything on by one `TICK`, with one input for each ship
+    pub fn step(&mut self, inputs: &[ShipInput]) {
+        self.frame += 1;
+        if self.over {
+            return;
+        }
+        let delta = Duration::from_secs_f32(TICK);
+        self.move_ships(inputs, delta);
+        self.move_ufo(delta);
+
+        for asteroid in self.asteroids.iter_mut() {
+            asteroid.position = wrap_position(
+                asteroid.position + asteroid.velocity * TICK,
+                self.half_extents,
+            );
+            asteroid.angle += ASTEROID_SPIN * TICK;
+        }
+        let bounds = self.half_extents + LASER_MARGIN;
+        self.lasers.retain_mut(|laser| {
+            laser.position += laser.velocity * TICK;
+            laser.position.x.abs() <= bounds.x && laser.position.y.abs() <= bounds.y
+        });
+        let half_extents = self.half_extents;
+        self.bullets.retain_mut(|bullet| {
+            bullet.position += bullet.velocity * TICK;
+            !offscreen(bullet.position, half_extents)
+        });
+
+        self.collide();
+        self.respawn(delta);
+        self.spawn_deferred();
+        if self.asteroids.is_empty() && self.deferred.is_empty() {
+            self.level += 1;
+            self.spawn_wave();
+        }
+    }
+
+    fn move_ships(&mut self, inputs: &[ShipInput], delta: Duration) {
+        let preset = self.difficulty.preset();
+        let thrust = preset.thrust / ship_mass();
+        for (id, input) in inputs.iter().enumerate().take(self.ships.len()) {
+            let ship = &mut self.ships[id];
+            if !ship.alive {
+                continue;
+            }
+            ship.angle += rotation(input, TURN_SPEED);
+            let up = heading(ship.angle).perp();
+            if input.thrust {
+                ship.velocity += up * thrust;
+            }
+            // the way rapier applies damping
+            ship.velocity /= 1.0 + TICK * preset.damping;
+            ship.position = wrap_position(ship.position + ship.velocity * TICK, self.half_extents);
+
+            if ship
+                .fire_timer
+                .fire(delta, preset.fire_cooldown, input.fire)
+            {
+                self.lasers.push(SimLaser {
+                    id: self.next_id,
+                    owner: Some(id),
+                    position: ship.position + up * SHIP_HEIGHT,
+                    velocity: up * LASER_SPEED,
+                    angle: ship.angle,
+                });
+                self.next_id += 1;
+            }
+        }
+    }
+
+    /// bring on, steer and fire the UFO, in the modes that have one
+    fn move_ufo(&mut self, delta: Duration) {
+        if matches!(self.mode, GameMode::Versus | GameMode::Sandbox) {
+            return;
+        }
+        let aggression = self.difficulty.preset().ufo_aggression;
+        let Some(saucer) = self.ufo.as_mut() else {
+            if self.ufo_timer.tick(delta).just_finished() {
+                self.ufo_timer = Timer::from_seconds(spawn_interval(aggression), TimerMode::Once);
+                let (position, velocity) = entry(&mut self.rng, self.half_extents);
+                self.ufo = Some(SimUfo {
+                    ufo: Ufo::new(aggression),
+                    position,
+                    velocity,
+                });
+            }
+            return;
+        };
+
+        let turned = saucer.ufo.turn_timer.tick(delta).just_finished();
+        saucer.velocity.y = steer(
+            &mut self.rng,
+            saucer.position.y,
+            saucer.velocity.y,
+            self.half_extents.y * 0.8,
+            turned,
+        );
+        if saucer.ufo.fire_timer.tick(delta).just_finished() {
+            let target = self
+                .ships
+                .iter()
+                .find(|ship| ship.alive)
+                .map(|ship| ship.position);
+            let direction = aim(&mut self.rng, saucer.position, target, aggression);
+            self.bullets.push(SimLaser {
+                id: self.next_id,
+                owner: None,
+                position: muzzle(saucer.position, direction),
+                velocity: direction * ENEMY_LASER_SPEED,
+                angle: 0.0,
+            });
+            self.next_id += 1;
+        }
+        saucer.position += saucer.velocity * TICK;
+        if offscreen(saucer.position, self.half_extents) {
+            self.ufo = None;
+        }
+    }
+
+    /// everything that can be hit, in the order it is stored
+    fn things(&self) -> Vec<(Thing, Side, &Collider, Vec2, f32)> {
+        let ships = self
+            .ships
+            .iter()
+            .enumerate()
+            .filter(|(_, ship)| ship.alive)
+            .map(|(id, ship)| {
+                (
+                    Thing::Ship(id),
+                    (Body::Ship, Some(Player(id))),
+                    &self.colliders.ship,
+                    ship.position,
+                    ship.angle,
+                )
+            });
+        let asteroids = self.asteroids.iter().enumerate().map(|(a, asteroid)| {
+            (
+                Thing::Asteroid(a),
+                (Body::Asteroid, None),
+                self.colliders.asteroid(asteroid.size),
+                asteroid.position,
+                asteroid.angle,
+            )
+        });
+        let lasers = self.lasers.iter().enumerate().map(|(l, laser)| {
+            (
+                Thing::Laser(l),
+                (Body::Laser, laser.owner.map(Player)),
+                &self.colliders.laser,
+                laser.position,
+                laser.angle,
+            )
+        });
+        let bullets = self.bullets.iter().enumerate().map(|(b, bullet)| {
+            (
+                Thing::Bullet(b),
+                (Body::EnemyLaser, None),
+                &self.colliders.bullet,
+                bullet.position,
+                bullet.angle,
+            )
+        });
+        let ufo = self.ufo.iter().map(|saucer| {
+            (
+                Thing::Ufo,
+                (Body::Ufo, None),
+                &self.colliders.saucer,
+                saucer.position,
+                0.0,
+            )
+        });
+        ships
+            .chain(asteroids)
+            .chain(lasers)
+            .chain(bullets)
+            .chain(ufo)
+            .collect()
+    }
+
+    /// Every pair is checked in the order things are stored, so both sides agree on
+    /// what hit what when several things touch at once. Near an edge, whatever is
+    /// closest across it is what gets checked, as its ghost would be in the game
+    fn collide(&mut self) {
+        let things = self.things();
+        let mut used = HashSet::new();
+        let mut contacts = Vec::new();
+        for (i, first) in things.iter().enumerate() {
+            for second in things[i + 1..].iter() {
+                if used.contains(&first.0) || used.contains(&second.0) {
+                    continue;
+                }
+                let ((a, side_a), (b, side_b)) = ordered((first, first.1), (second, second.1));
+                let Some(contact) = contact(&self.mode, false, side_a, side_b) else {
+                    continue;
+                };
+                let b_position = nearest_image(b.3, a.3, self.half_extents);
+                if !touching(a.2, (a.3, a.4), b.2, (b_position, b.4)) {
+                    continue;
+                }
+                used.insert(a.0);
+                if contact.spends_both() {
+                    used.insert(b.0);
+                }
+                contacts.push((a.0, b.0, contact));
+            }
+        }
+
+        let mut spent = HashSet::new();
+        let mut destroyed = Vec::new();
+        for (a, b, contact) in contacts {
+            match (a, contact) {
+                (Thing::Ship(id), Contact::Ship { by }) => {
+                    if by.is_some() {
+                        spent.insert(b);
+                    }
+                    self.destroy_ship(id, by);
+                }
+                (Thing::Asteroid(index), Contact::Asteroid { by }) => {
+                    spent.insert(b);
+                    destroyed.push((index, by));
+                }
+                (Thing::Laser(_), Contact::Ufo { by }) => {
+                    spent.insert(a);
+                    self.ufo = None;
+                    award(&mut self.players, &self.mode, by, UFO_POINTS);
+                }
+                _ => {}
+            }
+        }
+
+        let mut index = 0;
+        self.lasers.retain(|_| {
+            index += 1;
+            !spent.contains(&Thing::Laser(index - 1))
+        });
+
+        destroyed.sort_by_key(|(index, _)| *index);
+        let removed: Vec<_> = destroyed
+            .into_iter()
+            .rev()
+            .map(|(index, by)| (self.asteroids.remove(index), by))
+            .collect();
+        for (asteroid, by) in removed.into_iter().rev() {
+            award(&mut self.players, &self.mode, by, points(asteroid.size));
+            let Some(size) = fragment(asteroid.size) else {
+                continue;
+            };
+            for _ in 0..FRAGMENTS {
+                self.add_asteroid(size, asteroid.position);
+            }
+        }
+    }
+
+    /// what happens in the game when a ship is destroyed: lose_life, then count_kills
+    fn destroy_ship(&mut self, id: usize, by: Option<Player>) {
+        self.ships[id].alive = false;
+        if self.players.ship_lost(Player(id), &self.mode) && self.players.all_out() {
+            self.over = true;
+        }
+        let Some(killer) = by.filter(|_| self.mode == GameMode::Versus) else {
+            return;
+        };
+        match count_kill(&mut self.players, &mut self.rounds, killer) {
+            Kill::Counted => {}
+            Kill::MatchWon => self.over = true,
+            Kill::RoundWon => {
+                for ship in self.ships.iter_mut() {
+                    ship.alive = false;
+                }
+                self.lasers.clear();
+            }
+        }
+    }
+
+    /// bring ships back where they started, once they are due and it is clear
+    fn respawn(&mut self, delta: Duration) {
+        let count = self.ships.len();
+        for id in 0..count {
+            let start = start_position(Player(id), count);
+            let clear = respawn_clear(
+                start,
+                self.asteroids.iter().map(|asteroid| asteroid.position),
+            );
+            if self.players.0[id].respawn_due(delta, clear) {
+                self.ships[id] = self.new_ship(start);
+            }
+        }
+    }
+
+    fn spawn_area(&self) -> SpawnArea {
+        SpawnArea::new(
+            self.half_extents,
+            self.ships
+                .iter()
+                .filter(|ship| ship.alive)
+                .map(|ship| ship.position),
+            self.asteroids
+                .iter()
+                .map(|asteroid| (asteroid.size, asteroid.position)),
+        )
+    }
+
+    /// large asteroids around the edges
+    fn spawn_wave(&mut self) {
+        let count = wave_size(&self.mode, self.level, &DailyRun::default());
+        self.deferred
+            .extend((0..count).map(|_| AsteroidSize::Large));
+        self.spawn_deferred();
+    }
+
+    /// put in the asteroids there is room for now
+    fn spawn_deferred(&mut self) {
+        if self.deferred.is_empty() {
+            return;
+        }
+        let mut area = self.spawn_area();
+        for size in std::mem::take(&mut self.deferred) {
+            if let Some(position) = area.place(&mut self.rng, size, &mut self.deferred) {
+                self.add_asteroid(size, position);
+            }
+        }
+    }
+
+    fn add_asteroid(&mut self, size: AsteroidSize, position: Vec2) {
+        let velocity = asteroid_velocity(&mut self.rng, self.difficulty);
+        self.asteroids.push(SimAsteroid {
+            id: self.next_id,
+            size,
+            position,
+            velocity,
+            angle: 0.0,
+        });
+        self.next_id += 1;
+    }
+
+    /// A hash of everything in the game. When the two sides get a different one for
+    /// the same frame they have drifted apart
+    pub fn checksum(&self) -> u64 {
+        let mut hash = Fnv::default();
+        hash.add(self.frame);
+        hash.add(self.level);
+        hash.add(self.next_id);
+        hash.add(self.over as u32);
+        hash.add_time(self.ufo_timer.elapsed());
+        for player in self.players.0.iter() {
+            hash.add(player.score);
+            hash.add(player.lives);
+            hash.add_time(
+                player
+                    .respawn
+                    .as_ref()
+                    .map_or(Duration::MAX, Timer::elapsed),
+            );
+        }
+        hash.add(self.rounds.number);
+        for wins in self.rounds.wins.iter() {
+            hash.add(*wins);
+        }
+        for ship in self.ships.iter() {
+            hash.add_vec(ship.position);
+            hash.add_vec(ship.velocity);
+            hash.add(ship.angle.to_bits());
+            hash.add(ship.alive as u32);
+            hash.add_time(ship.fire_timer.0.elapsed());
+        }
+        for asteroid in self.asteroids.iter() {
+            hash.add(asteroid.id);
+            hash.add_vec(asteroid.position);
+            hash.add_vec(asteroid.velocity);
+        }
+        for laser in self.lasers.iter().chain(self.bullets.iter()) {
+            hash.add(laser.id);
+            hash.add_vec(laser.position);
+        }
+        if let Some(saucer) = self.ufo.as_ref() {
+            hash.add_vec(saucer.position);
+            hash.add_vec(saucer.velocity);
+            hash.add_time(saucer.ufo.fire_timer.elapsed());
+            hash.add_time(saucer.ufo.turn_timer.elapsed());
+        }
+        for size in self.deferred.iter() {
+            hash.add(*size as u32);
+        }
+        hash.0
+    }
+}
+
+/// 64 bit FNV-1a
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn add(&mut self, value: u32) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn add_vec(&mut self, value: Vec2) {
+        self.add(value.x.to_bits());
+        self.add(value.y.to_bits());
+    }
+
+    fn add_time(&mut self, value: Duration) {
+        self.add(value.as_secs() as u32);
+        self.add(value.subsec_nanos());
+    }
+}
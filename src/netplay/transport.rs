@@ -0,0 +1,303 @@
+use super::sim::Rules;
+use crate::components::ShipInput;
+use crate::settings::Difficulty;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// what the two sides of an online game tell each other
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    /// sent by the host until the other side answers with the same
+    Hello(Rules),
+    /// the sender's inputs from frame `start` on, and `ack`, the first frame of the
+    /// receiver's inputs the sender hasn't got yet. Inputs are sent again until
+    /// they are acknowledged, so a lost packet doesn't lose any
+    Input {
+        ack: u32,
+        start: u32,
+        inputs: Vec<ShipInput>,
+    },
+    /// the checksum of the game at the start of `frame`, to compare with ours
+    Checksum { frame: u32, value: u64 },
+}
+
+const HELLO: u8 = 0;
+const INPUT: u8 = 1;
+const CHECKSUM: u8 = 2;
+/// sent as their place in here
+const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Easy,
+    Difficulty::Normal,
+    Difficulty::Hard,
+    Difficulty::Arcade,
+];
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Packet::Hello(rules) => {
+                bytes.push(HELLO);
+                bytes.extend(rules.seed.to_le_bytes());
+                bytes.push(rules.versus as u8);
+                bytes.push(
+                    DIFFICULTIES
+                        .iter()
+                        .position(|difficulty| *difficulty == rules.difficulty)
+                        .unwrap_or_default() as u8,
+                );
+            }
+            Packet::Input { ack, start, inputs } => {
+                bytes.push(INPUT);
+                bytes.extend(ack.to_le_bytes());
+                bytes.extend(start.to_le_bytes());
+                bytes.extend(inputs.iter().map(|input| input.to_bits()));
+            }
+            Packet::Checksum { frame, value } => {
+                bytes.push(CHECKSUM);
+                bytes.extend(frame.to_le_bytes());
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// `None` for anything that isn't a packet of ours
+    pub fn decode(bytes: &[u8]) -> Option<Packet> {
+        let (tag, rest) = bytes.split_first()?;
+        match *tag {
+            HELLO if rest.len() == 10 => Some(Packet::Hello(Rules {
+                seed: u64::from_le_bytes(rest[..8].try_into().ok()?),
+                versus: rest[8] != 0,
+                difficulty: *DIFFICULTIES.get(rest[9] as usize)?,
+            })),
+            INPUT if rest.len() >= 8 => Some(Packet::Input {
+                ack: u32::from_le_bytes(rest[..4].try_into().ok()?),
+                start: u32::from_le_bytes(rest[4..8].try_into().ok()?),
+                inputs: rest[8..]
+                    .iter()
+                    .map(|bits| ShipInput::from_bits(*bits))
+                    .collect(),
+            }),
+            CHECKSUM if rest.len() == 12 => Some(Packet::Checksum {
+                frame: u32::from_le_bytes(rest[..4].try_into().ok()?),
+                value: u64::from_le_bytes(rest[4..].try_into().ok()?),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A way of getting packets to the other side. Delivery may be late, out of order or
+/// not at all; the rollback session copes with all three. A browser build needs a
+/// WebRTC data channel behind this, which isn't written yet
+pub trait Transport: Send + Sync {
+    fn send(&mut self, packet: &Packet);
+    /// whatever has arrived since the last call
+    fn receive(&mut self) -> Vec<Packet>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use udp::UdpTransport;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod udp {
+    use super::*;
+    use std::net::{SocketAddr, UdpSocket};
+
+    /// plain UDP, for native builds
+    pub struct UdpTransport {
+        socket: UdpSocket,
+        /// the host learns it from the first packet that comes in
+        peer: Option<SocketAddr>,
+    }
+
+    impl UdpTransport {
+        /// wait for someone to join on `port`
+        pub fn host(port: u16) -> std::io::Result<Self> {
+            Self::bind(SocketAddr::from(([0, 0, 0, 0], port)), None)
+        }
+
+        /// join the game hosted at `address`
+        pub fn join(address: &str) -> std::io::Result<Self> {
+            let peer: SocketAddr = address
+                .parse()
+                .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+            Self::bind(SocketAddr::from(([0, 0, 0, 0], 0)), Some(peer))
+        }
+
+        fn bind(local: SocketAddr, peer: Option<SocketAddr>) -> std::io::Result<Self> {
+            let socket = UdpSocket::bind(local)?;
+            socket.set_nonblocking(true)?;
+            Ok(Self { socket, peer })
+        }
+    }
+
+    impl Transport for UdpTransport {
+        fn send(&mut self, packet: &Packet) {
+            let Some(peer) = self.peer else {
+                return;
+            };
+            if let Err(error) = self.socket.send_to(&packet.encode(), peer) {
+                warn!("could not send to {}: {}", peer, error);
+            }
+        }
+
+        fn receive(&mut self) -> Vec<Packet> {
+            let mut packets = Vec::new();
+            let mut buffer = [0; 1500];
+            // stops at WouldBlock, once there is nothing more waiting
+            while let Ok((length, from)) = self.socket.recv_from(&mut buffer) {
+                if self.peer.is_some_and(|peer| peer != from) {
+                    continue;
+                }
+                if let Some(packet) = Packet::decode(&buffer[..length]) {
+                    self.peer = Some(from);
+                    packets.push(packet);
+                }
+            }
+            packets
+        }
+    }
+}
+
+/// How bad the pretend network between two loopback transports is
+#[derive(Clone)]
+pub struct Conditions {
+    pub latency: Duration,
+    /// each packet is late by up to this much more, so they arrive out of order
+    pub jitter: Duration,
+    /// 0.0 to 1.0, the share of packets that never arrive
+    pub loss: f32,
+    /// for which packets are lost and how late they are, so a run can be repeated
+    pub seed: u64,
+    pub clock: Clock,
+}
+
+/// What loopback transports tell the time by. It is moved on by whoever drives the
+/// sessions, a step at a time, so a run doesn't depend on how fast it goes
+#[derive(Clone, Default)]
+pub struct Clock(Arc<Mutex<Duration>>);
+
+impl Clock {
+    /// the time since the clock started
+    pub fn now(&self) -> Duration {
+        self.0.lock().map_or(Duration::ZERO, |now| *now)
+    }
+
+    pub fn advance(&self, by: Duration) {
+        if let Ok(mut now) = self.0.lock() {
+            *now += by;
+        }
+    }
+}
+
+/// packets on their way one way, with when they arrive
+type Link = Arc<Mutex<VecDeque<(Duration, Vec<u8>)>>>;
+
+/// Both sides of a game in the one process, connected through a pretend network.
+/// Packets are encoded and decoded like they would be for real
+pub struct LoopbackTransport {
+    outgoing: Link,
+    incoming: Link,
+    conditions: Conditions,
+    rng: StdRng,
+}
+
+impl LoopbackTransport {
+    pub fn pair(conditions: Conditions) -> (Self, Self) {
+        let (a, b) = (Link::default(), Link::default());
+        // each direction loses its own packets
+        let seed = conditions.seed;
+        (
+            Self {
+                outgoing: a.clone(),
+                incoming: b.clone(),
+                conditions: conditions.clone(),
+                rng: StdRng::seed_from_u64(seed),
+            },
+            Self {
+                outgoing: b,
+                incoming: a,
+                conditions,
+                rng: StdRng::seed_from_u64(!seed),
+            },
+        )
+    }
+}
+
+impl Transport for LoopbackTransport {
+    fn send(&mut self, packet: &Packet) {
+        if self.rng.gen::<f32>() < self.conditions.loss {
+            return;
+        }
+        let jitter = self.conditions.jitter.mul_f32(self.rng.gen());
+        let arrival = self.conditions.clock.now() + self.conditions.latency + jitter;
+        if let Ok(mut link) = self.outgoing.lock() {
+            link.push_back((arrival, packet.encode()));
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Packet> {
+        let Ok(mut link) = self.incoming.lock() else {
+            return Vec::new();
+        };
+        let now = self.conditions.clock.now();
+        let mut packets = Vec::new();
+        link.retain(|(arrival, bytes)| {
+            if *arrival > now {
+                return true;
+            }
+            packets.extend(Packet::decode(bytes));
+            false
+        });
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_survive_encoding() {
+        let packets = [
+            Packet::Hello(Rules {
+                seed: 0x0123_4567_89ab_cdef,
+                versus: true,
+                difficulty: Difficulty::Arcade,
+            }),
+            Packet::Input {
+                ack: 7,
+                start: 1_000_000,
+                inputs: vec![
+                    ShipInput::default(),
+                    ShipInput {
+                        left: true,
+                        right: false,
+                        thrust: true,
+                        fire: true,
+                    },
+                ],
+            },
+            Packet::Checksum {
+                frame: 30,
+                value: u64::MAX,
+            },
+        ];
+        for packet in packets {
+            assert_eq!(Packet::decode(&packet.encode()), Some(packet));
+        }
+    }
+
+    #[test]
+    fn other_bytes_are_not_packets() {
+        assert_eq!(Packet::decode(&[]), None);
+        assert_eq!(Packet::decode(&[CHECKSUM, 1, 2, 3]), None);
+        assert_eq!(Packet::decode(&[42; 13]), None);
+    }
+}
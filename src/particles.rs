@@ -0,0 +1,259 @@
+use crate::components::*;
+use crate::events::{AsteroidDestroyed, ShipDestroyed, UfoDestroyed};
+use crate::resources::GameTime;
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use crate::ship::{destroy_ship, ShipControl, SHIP_POINTS};
+use bevy::prelude::*;
+use rand::*;
+
+/// the flame behind the ship, an open V below its tail
+pub const FLAME_POINTS: [Vec2; 3] = [
+    Vec2::new(-7.0, -22.5),
+    Vec2::new(0.0, -34.0),
+    Vec2::new(7.0, -22.5),
+];
+
+/// how many debris pieces can be flying around at once. They are all spawned up front
+/// and reused, so an explosion never has to build new meshes
+const POOL_SIZE: usize = 256;
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ParticlePool(Vec::with_capacity(POOL_SIZE)))
+            .add_startup_system(fill_pool);
+
+        for state in [GameState::Title, GameState::Playing] {
+            app.add_system_set(
+                SystemSet::on_update(state)
+                    // in the same frame: losing the last ship ends the game before
+                    // the next one
+                    .with_system(spawn_debris.after(destroy_ship))
+                    .with_system(attach_exhaust)
+                    .with_system(flicker_exhaust.after(ShipControl)),
+            );
+        }
+        // the debris of the last ship keeps flying behind the game over screen
+        for state in [GameState::Title, GameState::Playing, GameState::GameOver] {
+            app.add_system_set(SystemSet::on_update(state).with_system(update_particles));
+        }
+        // cleared when the demo or the game over screen is left, a new game starts or
+        // a paused game is quit
+        app.add_system_set(SystemSet::on_exit(GameState::Title).with_system(clear_particles))
+            .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(clear_particles))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(clear_particles))
+            .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(clear_particles));
+    }
+}
+
+/// the particles that are currently hidden and free to use
+#[derive(Resource)]
+struct ParticlePool(Vec<Entity>);
+
+fn fill_pool(mut commands: Commands, shapes: Res<ShapeCache>, mut pool: ResMut<ParticlePool>) {
+    for _ in 0..POOL_SIZE {
+        let entity = commands
+            .spawn(shapes.bundle(ShapeKind::Debris, Transform::default()))
+            .insert(Visibility { is_visible: false })
+            .insert(Particle {
+                velocity: Vec2::ZERO,
+                spin: 0.0,
+                life: Timer::default(),
+            })
+            .id();
+        pool.0.push(entity);
+    }
+}
+
+/// Take a particle out of the pool and send it flying. When the pool has run dry
+/// the piece is simply left out
+fn emit(
+    pool: &mut ParticlePool,
+    particles: &mut Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+    transform: Transform,
+    velocity: Vec2,
+    spin: f32,
+    lifetime: f32,
+) {
+    let Some(entity) = pool.0.pop() else {
+        return;
+    };
+    if let Ok((mut particle, mut particle_transform, mut visibility)) = particles.get_mut(entity) {
+        *particle = Particle {
+            velocity,
+            spin,
+            life: Timer::from_seconds(lifetime, TimerMode::Once),
+        };
+        *particle_transform = transform;
+        visibility.is_visible = true;
+    }
+}
+
+/// how much debris an explosion throws around
+struct Burst {
+    pieces: usize,
+    /// of the longest pieces
+    length: f32,
+    /// of the fastest pieces, on top of the exploded body's own speed
+    speed: f32,
+}
+
+/// Break whatever was destroyed into line segments that keep its momentum
+fn spawn_debris(
+    mut asteroid_events: EventReader<AsteroidDestroyed>,
+    mut ufo_events: EventReader<UfoDestroyed>,
+    mut ship_events: EventReader<ShipDestroyed>,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+) {
+    let mut rng = rand::thread_rng();
+    for destroyed in asteroid_events.iter() {
+        let burst = match destroyed.size {
+            AsteroidSize::Large => Burst {
+                pieces: 14,
+                length: 10.0,
+                speed: 120.0,
+            },
+            AsteroidSize::Medium => Burst {
+                pieces: 10,
+                length: 8.0,
+                speed: 100.0,
+            },
+            AsteroidSize::Small => Burst {
+                pieces: 6,
+                length: 5.0,
+                speed: 80.0,
+            },
+        };
+        let origin = Transform::from_translation(destroyed.position.extend(1.0));
+        scatter(&mut pool, &mut particles, origin, destroyed.velocity, burst);
+    }
+
+    for destroyed in ufo_events.iter() {
+        let burst = Burst {
+            pieces: 10,
+            length: 6.0,
+            speed: 150.0,
+        };
+        let origin = Transform::from_translation(destroyed.position.extend(1.0));
+        scatter(&mut pool, &mut particles, origin, destroyed.velocity, burst);
+    }
+
+    for destroyed in ship_events.iter() {
+        let origin = destroyed.position.extend(1.0);
+        // each edge of the triangle drifts away from the middle, tumbling slowly
+        for (i, start) in SHIP_POINTS.iter().enumerate() {
+            let end = SHIP_POINTS[(i + 1) % SHIP_POINTS.len()];
+            let middle = destroyed.rotation * ((*start + end) / 2.0).extend(0.0);
+            let edge = end - *start;
+            let transform = Transform {
+                translation: origin + middle,
+                rotation: destroyed.rotation * Quat::from_rotation_z(edge.y.atan2(edge.x)),
+                scale: Vec3::new(edge.length(), 1.0, 1.0),
+            };
+            let drift = middle.truncate().normalize_or_zero() * rng.gen_range(15.0..40.0);
+            emit(
+                &mut pool,
+                &mut particles,
+                transform,
+                destroyed.velocity + drift,
+                rng.gen_range(-2.0..2.0),
+                rng.gen_range(1.5..2.5),
+            );
+        }
+    }
+}
+
+/// short pieces flying off in every direction from `origin`
+fn scatter(
+    pool: &mut ParticlePool,
+    particles: &mut Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+    origin: Transform,
+    velocity: Vec2,
+    burst: Burst,
+) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..burst.pieces {
+        let direction = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU));
+        let transform = Transform {
+            rotation: Quat::from_rotation_z(rng.gen_range(0.0..std::f32::consts::TAU)),
+            scale: Vec3::new(rng.gen_range(burst.length * 0.4..burst.length), 1.0, 1.0),
+            ..origin
+        };
+        emit(
+            pool,
+            particles,
+            transform,
+            velocity + direction * rng.gen_range(burst.speed * 0.3..burst.speed),
+            rng.gen_range(-6.0..6.0),
+            rng.gen_range(0.4..1.0),
+        );
+    }
+}
+
+/// move the live particles and put the burnt out ones back in the pool
+fn update_particles(
+    time: GameTime,
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Visibility)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut visibility) in particles.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+        transform.translation += (particle.velocity * delta).extend(0.0);
+        transform.rotate_z(particle.spin * delta);
+        if particle.life.tick(time.delta()).finished() {
+            visibility.is_visible = false;
+            pool.0.push(entity);
+        }
+    }
+}
+
+fn clear_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(Entity, &mut Visibility), With<Particle>>,
+) {
+    for (entity, mut visibility) in particles.iter_mut() {
+        if visibility.is_visible {
+            visibility.is_visible = false;
+            pool.0.push(entity);
+        }
+    }
+}
+
+/// give every new ship a (hidden) flame behind it
+fn attach_exhaust(
+    mut commands: Commands,
+    shapes: Res<ShapeCache>,
+    ships: Query<Entity, Added<Ship>>,
+) {
+    for ship in ships.iter() {
+        commands.entity(ship).with_children(|parent| {
+            parent
+                .spawn(shapes.bundle(ShapeKind::Flame, Transform::default()))
+                .insert(Visibility { is_visible: false })
+                .insert(Exhaust);
+        });
+    }
+}
+
+/// the flame shows every other frame while thrusting, like on the old vector screens
+fn flicker_exhaust(
+    mut blink: Local<bool>,
+    ships: Query<(&ShipInput, &Children)>,
+    mut exhausts: Query<&mut Visibility, With<Exhaust>>,
+) {
+    *blink = !*blink;
+    for (input, children) in ships.iter() {
+        let lit = input.thrust && *blink;
+        let mut flames = exhausts.iter_many_mut(children);
+        while let Some(mut visibility) = flames.fetch_next() {
+            if visibility.is_visible != lit {
+                visibility.is_visible = lit;
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::asteroid::{asteroid_collider, radius};
+use crate::components::*;
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use crate::ship::laser_collider;
+use bevy::ecs::system::Command;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier2d::prelude::*;
+
+/// how many of each kind are built up front. A busy field can need more, in which
+/// case the pool grows and keeps the extra ones for next time
+const INITIAL_SIZES: [(Pooled, usize); 4] = [
+    (Pooled::Laser, 32),
+    (Pooled::Asteroid(AsteroidSize::Large), 16),
+    (Pooled::Asteroid(AsteroidSize::Medium), 48),
+    (Pooled::Asteroid(AsteroidSize::Small), 128),
+];
+
+/// Lasers and asteroids are recycled instead of spawned and despawned. Setting up
+/// a new one (and its collider) every shot and every split is slow enough to stutter
+/// in the browser
+pub struct PoolPlugin;
+
+impl Plugin for PoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Pool::new())
+            .add_startup_system(fill_pool);
+    }
+}
+
+/// The parked entities of each kind, and the colliders they get while in use
+#[derive(Resource)]
+pub struct Pool {
+    free: HashMap<Pooled, Vec<Entity>>,
+    colliders: HashMap<Pooled, Collider>,
+    /// every entity the pool has made so far
+    built: usize,
+}
+
+impl Pool {
+    fn new() -> Self {
+        let colliders = INITIAL_SIZES
+            .iter()
+            .map(|(kind, _)| {
+                let collider = match kind {
+                    Pooled::Laser => laser_collider(),
+                    Pooled::Asteroid(size) => asteroid_collider(*size),
+                };
+                (*kind, collider)
+            })
+            .collect();
+        Self {
+            free: HashMap::default(),
+            colliders,
+            built: 0,
+        }
+    }
+
+    /// how many entities the pool has made, parked or not. This stops going up once
+    /// it is big enough for the game
+    pub fn built(&self) -> usize {
+        self.built
+    }
+
+    /// a parked entity of `kind`, or a new one when they are all in use
+    fn take(&mut self, commands: &mut Commands, kind: Pooled) -> Entity {
+        if let Some(entity) = self.free.get_mut(&kind).and_then(Vec::pop) {
+            return entity;
+        }
+        self.built += 1;
+        let entity = commands.spawn_empty().id();
+        commands.add(Build { entity, kind });
+        entity
+    }
+
+    /// Put a laser fired by `owner` in play
+    pub fn fire_laser(
+        &mut self,
+        commands: &mut Commands,
+        owner: Player,
+        transform: Transform,
+        velocity: Vec2,
+    ) {
+        let entity = self.take(commands, Pooled::Laser);
+        commands.entity(entity).remove::<Parked>().insert((
+            transform,
+            Velocity::linear(velocity),
+            Visibility { is_visible: true },
+            self.colliders[&Pooled::Laser].clone(),
+            body(),
+            Laser { owner },
+        ));
+    }
+
+    /// Put an asteroid in play
+    pub fn launch_asteroid(
+        &mut self,
+        commands: &mut Commands,
+        size: AsteroidSize,
+        transform: Transform,
+        velocity: Velocity,
+    ) {
+        let kind = Pooled::Asteroid(size);
+        let entity = self.take(commands, kind);
+        commands.entity(entity).remove::<Parked>().insert((
+            transform,
+            velocity,
+            Visibility { is_visible: true },
+            self.colliders[&kind].clone(),
+            body(),
+            Wrapper,
+            Asteroid(size),
+        ));
+    }
+}
+
+/// The rigid body a pooled entity has while in play. Parked ones go without, so
+/// they cost the physics nothing
+fn body() -> (RigidBody, Sleeping, Ccd) {
+    (RigidBody::Dynamic, Sleeping::disabled(), Ccd::enabled())
+}
+
+/// Take a laser or an asteroid out of play, parking it for reuse. Anything that
+/// isn't pooled (like the UFO's lasers) is despawned
+pub fn release(commands: &mut Commands, entity: Entity) {
+    commands.add(Release(entity));
+}
+
+fn fill_pool(mut commands: Commands, mut pool: ResMut<Pool>) {
+    for (kind, count) in INITIAL_SIZES {
+        for _ in 0..count {
+            let entity = commands.spawn_empty().id();
+            commands.add(Build { entity, kind });
+            pool.free.entry(kind).or_default().push(entity);
+        }
+        pool.built += count;
+    }
+}
+
+/// Give a pooled entity its (shared) mesh and physics settings, parked. As a command so
+/// callers of `Pool::take` don't need the shape cache
+struct Build {
+    entity: Entity,
+    kind: Pooled,
+}
+
+impl Command for Build {
+    fn write(self, world: &mut World) {
+        let shape = match self.kind {
+            Pooled::Laser => ShapeKind::Laser,
+            Pooled::Asteroid(size) => ShapeKind::Asteroid(size),
+        };
+        let bundle = world
+            .resource::<ShapeCache>()
+            .bundle(shape, Transform::default());
+        let mut entity = world.entity_mut(self.entity);
+        entity.insert((
+            bundle,
+            GravityScale(0.0),
+            ActiveEvents::COLLISION_EVENTS,
+            Sensor,
+            Velocity::zero(),
+            self.kind,
+            Parked,
+        ));
+        entity.insert(Visibility { is_visible: false });
+        if let Pooled::Asteroid(size) = self.kind {
+            let radius = radius(size);
+            entity.insert(Dimensions {
+                width: radius * 2.0,
+                height: radius * 2.0,
+            });
+        }
+    }
+}
+
+struct Release(Entity);
+
+impl Command for Release {
+    fn write(self, world: &mut World) {
+        let Some(mut entity) = world.get_entity_mut(self.0) else {
+            return;
+        };
+        let Some(&kind) = entity.get::<Pooled>() else {
+            entity.despawn();
+            return;
+        };
+        // already released, e.g. when something is destroyed and the state ends in
+        // the same frame
+        if entity.contains::<Parked>() {
+            return;
+        }
+        // without a body or a collider it drops out of the physics world
+        entity
+            .remove_intersection::<(RigidBody, Sleeping, Ccd, Collider, Laser, Asteroid, Wrapper)>(
+            );
+        entity.insert((Parked, Velocity::zero(), Visibility { is_visible: false }));
+        world
+            .resource_mut::<Pool>()
+            .free
+            .entry(kind)
+            .or_default()
+            .push(self.0);
+    }
+}
@@ -1,18 +1,46 @@
+use crate::components::Player;
+use crate::settings::Difficulty;
 use bevy::{
-    prelude::{Color, Resource},
-    time::Stopwatch,
+    ecs::system::SystemParam,
+    prelude::{Color, Res, Resource, Time, Vec2},
+    time::{Timer, TimerMode},
+    utils::{Duration, HashMap},
 };
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 
 pub const FILL_COLOR: Color = Color::rgb(0.04, 0.04, 0.04);
 
-/// a timer that starts each time a laser is fired. Another laser cannot be fired
-/// until .2 seconds have lapsed
-#[derive(Resource)]
-pub struct FireTimer(pub Stopwatch);
+/// size of the playfield in world units, whatever the size of the window
+pub const ARENA_WIDTH: f32 = 1280.0;
+pub const ARENA_HEIGHT: f32 = 720.0;
+
+/// how many entries are kept in the high score table
+pub const MAX_HIGH_SCORES: usize = 10;
+
+/// The playfield, centered on the origin. The camera scales it to fit the window so
+/// every screen (and the browser canvas) gets the same amount of room
+#[derive(Resource, Clone, Copy)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+}
 
-impl Default for FireTimer {
+impl Default for Arena {
     fn default() -> Self {
-        Self(Stopwatch::new())
+        Self {
+            width: ARENA_WIDTH,
+            height: ARENA_HEIGHT,
+        }
+    }
+}
+
+impl Arena {
+    /// distance from the center to the edges
+    pub fn half_extents(&self) -> Vec2 {
+        Vec2::new(self.width, self.height) / 2.0
     }
 }
 
@@ -25,3 +53,431 @@ impl Default for Level {
         Level(1)
     }
 }
+
+impl Level {
+    /// the waves cleared to get here, which is what time attack counts
+    pub fn waves_cleared(&self) -> u32 {
+        self.0.saturating_sub(1)
+    }
+}
+
+/// The random numbers behind the asteroid field. Games with the same seed (like
+/// everyone's daily challenge) get the same waves
+#[derive(Resource)]
+pub struct GameRng {
+    pub seed: u64,
+    /// for everything but the waves, so it drifts apart as people play differently
+    pub rng: StdRng,
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(rand::random())
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Numbers for the wave of `level`. They don't depend on what happened in the
+    /// waves before, so a level starts the same way for everyone
+    pub fn wave(&self, level: u32) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (level as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+}
+
+/// How the game is played, picked on the mode select screen
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    /// as many waves as can be cleared before the clock runs out
+    TimeAttack,
+    /// asteroids keep coming, one after another, for as long as the ship lasts
+    Survival,
+    /// a single ship and no extra lives
+    OneLife,
+    /// the same seed and modifiers for everyone playing on the same day
+    Daily,
+    /// no dying, no waves, and tools to put asteroids wherever they are wanted
+    Sandbox,
+    /// two people on one keyboard, taking on the rocks together
+    Coop,
+    /// two people on one keyboard shooting at each other, in rounds
+    Versus,
+}
+
+impl GameMode {
+    /// the modes with a high score table of their own, in the order they are shown
+    pub const SCORE_TABLES: [GameMode; 4] = [
+        GameMode::Classic,
+        GameMode::TimeAttack,
+        GameMode::Survival,
+        GameMode::OneLife,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::TimeAttack => "Time Attack",
+            GameMode::Survival => "Survival",
+            GameMode::OneLife => "One Life",
+            GameMode::Daily => "Daily Challenge",
+            GameMode::Sandbox => "Sandbox",
+            GameMode::Coop => "Co-op",
+            GameMode::Versus => "Versus",
+        }
+    }
+
+    /// how many ships are flown by people
+    pub fn players(&self) -> usize {
+        match self {
+            GameMode::Coop | GameMode::Versus => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether losing a ship costs a life. Otherwise it just comes back after a moment
+    pub fn limited_lives(&self) -> bool {
+        !matches!(self, GameMode::TimeAttack | GameMode::Versus)
+    }
+
+    /// the high score table the mode's scores go in, if it has one. Co-op scores
+    /// count as classic ones, and daily challenges keep results of their own
+    pub fn score_table(&self) -> Option<GameMode> {
+        match self {
+            GameMode::Coop => Some(GameMode::Classic),
+            GameMode::Versus | GameMode::Daily | GameMode::Sandbox => None,
+            mode => Some(*mode),
+        }
+    }
+}
+
+/// Boosts for the players' guns. Only the sandbox and the console hand them out for now
+#[derive(Resource)]
+pub struct PowerUps {
+    /// the gun fires twice as fast
+    pub rapid_fire: bool,
+    /// lasers fired at once, in a fan
+    pub spread: u32,
+}
+
+impl Default for PowerUps {
+    fn default() -> Self {
+        Self {
+            rapid_fire: false,
+            spread: 1,
+        }
+    }
+}
+
+/// How fast the game runs, 1.0 being normal. The physics and everything timed in
+/// play goes by it; the menus, the camera and the sound don't
+#[derive(Resource)]
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// The time that passes in play this frame, which is the frame's time slowed down
+/// (or sped up) by `TimeScale`
+#[derive(SystemParam)]
+pub struct GameTime<'w, 's> {
+    time: Res<'w, Time>,
+    scale: Res<'w, TimeScale>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> GameTime<'w, 's> {
+    pub fn delta(&self) -> Duration {
+        self.time.delta().mul_f32(self.scale.0)
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.time.delta_seconds() * self.scale.0
+    }
+
+    /// for what happens by the frame rather than by the clock
+    pub fn scale(&self) -> f32 {
+        self.scale.0
+    }
+}
+
+/// Nothing can destroy the ship, turned on and off from the console
+#[derive(Resource, Default)]
+pub struct GodMode(pub bool);
+
+/// The console was used during the current game, so its result isn't put in the
+/// high scores or the daily results
+#[derive(Resource, Default)]
+pub struct Cheated(pub bool);
+
+/// how long a destroyed ship stays away
+pub const RESPAWN_SECONDS: f32 = 2.0;
+
+/// How one person is doing in the current game
+#[derive(Clone)]
+pub struct PlayerState {
+    /// points earned in the current game
+    pub score: u32,
+    /// ships left, including the one currently flying
+    pub lives: u32,
+    /// present while their ship is waiting to come back after being destroyed
+    pub respawn: Option<Timer>,
+}
+
+impl PlayerState {
+    /// their ship comes back in a moment
+    pub fn start_respawn(&mut self) {
+        self.respawn = Some(Timer::from_seconds(RESPAWN_SECONDS, TimerMode::Once));
+    }
+
+    /// Count down the respawn by `delta`. True, and the respawn over, once it is due
+    /// and the ship's starting point is `clear`
+    pub fn respawn_due(&mut self, delta: Duration, clear: bool) -> bool {
+        let Some(respawn) = self.respawn.as_mut() else {
+            return false;
+        };
+        let due = respawn.tick(delta).finished() && clear;
+        if due {
+            self.respawn = None;
+        }
+        due
+    }
+}
+
+/// Everyone in the current game, indexed by `Player`
+#[derive(Resource, Default, Clone)]
+pub struct Players(pub Vec<PlayerState>);
+
+impl Players {
+    pub fn new(count: usize, lives: u32) -> Self {
+        Self(
+            (0..count)
+                .map(|_| PlayerState {
+                    score: 0,
+                    lives,
+                    respawn: None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn get_mut(&mut self, player: Player) -> Option<&mut PlayerState> {
+        self.0.get_mut(player.0)
+    }
+
+    /// Take a life from `player`, starting their respawn if they have one left
+    pub fn lose_life(&mut self, player: Player) {
+        if let Some(state) = self.get_mut(player) {
+            state.lives = state.lives.saturating_sub(1);
+            if state.lives > 0 {
+                state.start_respawn();
+            }
+        }
+    }
+
+    /// `player`'s ship was destroyed. Where lives are limited that costs one, and
+    /// elsewhere the ship just comes back. True when a life was lost
+    pub fn ship_lost(&mut self, player: Player, mode: &GameMode) -> bool {
+        if mode.limited_lives() {
+            self.lose_life(player);
+            return true;
+        }
+        if let Some(state) = self.get_mut(player) {
+            state.start_respawn();
+        }
+        false
+    }
+
+    /// everyone starts a versus round from scratch, back where they began
+    pub fn start_round(&mut self) {
+        for player in self.0.iter_mut() {
+            player.score = 0;
+            player.respawn = None;
+            player.start_respawn();
+        }
+    }
+
+    /// the game goes on while anyone has a ship left
+    pub fn all_out(&self) -> bool {
+        self.0.iter().all(|state| state.lives == 0)
+    }
+}
+
+/// How a versus match is going
+#[derive(Resource, Default, Clone)]
+pub struct Rounds {
+    /// the round being played, counting from 1
+    pub number: u32,
+    /// rounds won by each player
+    pub wins: Vec<u32>,
+}
+
+impl Rounds {
+    pub fn new(players: usize) -> Self {
+        Self {
+            number: 1,
+            wins: vec![0; players],
+        }
+    }
+}
+
+/// A finished game in the high score table
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HighScore {
+    pub score: u32,
+    pub level: u32,
+    pub waves_cleared: u32,
+    /// how long the game lasted
+    pub seconds: u32,
+    pub difficulty: Difficulty,
+}
+
+impl HighScore {
+    /// What `table` ranks it by, higher being better: the waves cleared in time
+    /// attack, the time survived in survival and the score everywhere else. The
+    /// score breaks ties
+    fn rank(&self, table: GameMode) -> (u32, u32) {
+        match table {
+            GameMode::TimeAttack => (self.waves_cleared, self.score),
+            GameMode::Survival => (self.seconds, self.score),
+            _ => (self.score, 0),
+        }
+    }
+}
+
+/// The best games seen this session, best first, in a table for each of
+/// `GameMode::SCORE_TABLES`
+#[derive(Resource, Default)]
+pub struct HighScores(pub HashMap<GameMode, Vec<HighScore>>);
+
+impl HighScores {
+    pub fn table(&self, table: GameMode) -> &[HighScore] {
+        self.0.get(&table).map_or(&[], Vec::as_slice)
+    }
+
+    /// Add a game to a table. Returns true if it made the cut
+    pub fn submit(&mut self, table: GameMode, entry: HighScore) -> bool {
+        let rank = entry.rank(table);
+        if rank == (0, 0) {
+            return false;
+        }
+        let scores = self.0.entry(table).or_default();
+        let index = scores
+            .iter()
+            .position(|s| rank > s.rank(table))
+            .unwrap_or(scores.len());
+        if index >= MAX_HIGH_SCORES {
+            return false;
+        }
+        scores.insert(index, entry);
+        scores.truncate(MAX_HIGH_SCORES);
+        true
+    }
+}
+
+/// The index of the highlighted item in the active menu
+#[derive(Resource, Default)]
+pub struct MenuCursor(pub usize);
+
+/// Screen shake, hit-stop and zoom punch still to be played out on the game camera
+#[derive(Resource, Default)]
+pub struct CameraEffects {
+    /// 0.0 to 1.0. The shake grows with its square and wears off over time
+    pub trauma: f32,
+    /// seconds the action stays frozen for
+    pub hit_stop: f32,
+    /// how far the camera is zoomed in, as a fraction of the arena
+    pub zoom: f32,
+}
+
+impl CameraEffects {
+    pub fn shake(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+
+    /// freezes don't add up, the longest one wins
+    pub fn freeze(&mut self, seconds: f32) {
+        self.hit_stop = self.hit_stop.max(seconds);
+    }
+
+    pub fn punch(&mut self, zoom: f32) {
+        self.zoom = self.zoom.max(zoom);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u32, waves_cleared: u32, seconds: u32) -> HighScore {
+        HighScore {
+            score,
+            level: 1,
+            waves_cleared,
+            seconds,
+            difficulty: Difficulty::Normal,
+        }
+    }
+
+    /// the scores of `entries` as they end up in `table`
+    fn ranked(table: GameMode, entries: &[HighScore]) -> Vec<u32> {
+        let mut high_scores = HighScores::default();
+        for entry in entries {
+            high_scores.submit(table, *entry);
+        }
+        high_scores
+            .table(table)
+            .iter()
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn classic_ranks_by_score() {
+        let entries = [entry(100, 9, 900), entry(300, 1, 10), entry(200, 5, 50)];
+        assert_eq!(ranked(GameMode::Classic, &entries), [300, 200, 100]);
+    }
+
+    #[test]
+    fn time_attack_ranks_by_waves_then_score() {
+        let entries = [entry(900, 2, 0), entry(100, 4, 0), entry(300, 4, 0)];
+        assert_eq!(ranked(GameMode::TimeAttack, &entries), [300, 100, 900]);
+    }
+
+    #[test]
+    fn survival_ranks_by_time_then_score() {
+        let entries = [entry(900, 0, 60), entry(100, 0, 240), entry(300, 0, 240)];
+        assert_eq!(ranked(GameMode::Survival, &entries), [300, 100, 900]);
+    }
+
+    #[test]
+    fn empty_games_and_the_overflow_are_left_out() {
+        let mut high_scores = HighScores::default();
+        assert!(!high_scores.submit(GameMode::Classic, entry(0, 3, 100)));
+        // a survival run that lasted is worth keeping even without points
+        assert!(high_scores.submit(GameMode::Survival, entry(0, 0, 100)));
+
+        for score in 1..=MAX_HIGH_SCORES as u32 {
+            assert!(high_scores.submit(GameMode::Classic, entry(score * 10, 0, 0)));
+        }
+        assert!(!high_scores.submit(GameMode::Classic, entry(5, 0, 0)));
+        assert!(high_scores.submit(GameMode::Classic, entry(1000, 0, 0)));
+        let table = high_scores.table(GameMode::Classic);
+        assert_eq!(table.len(), MAX_HIGH_SCORES);
+        assert_eq!(
+            (table[0].score, table[MAX_HIGH_SCORES - 1].score),
+            (1000, 20)
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use crate::asteroid::{radius, ASTEROID_SPIN};
+use crate::camera::cursor_in_arena;
+use crate::components::*;
+use crate::console::{self, AddConsoleCommand, CommandError};
+use crate::pool::{self, Pool};
+use crate::resources::{Arena, GameMode, PowerUps, TimeScale};
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use crate::ship::SPREAD_SHOT;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+/// speed an asteroid gets for each unit the mouse is dragged
+const DRAG_SPEED: f32 = 1.5;
+/// the speeds T goes through, 1.0 being normal
+const TIME_SCALES: [f32; 4] = [1.0, 0.5, 0.25, 0.1];
+/// the fastest the console can set the physics to, past which things tunnel through
+/// each other
+const MAX_TIME_SCALE: f32 = 4.0;
+
+/// Sandbox: nothing can destroy the ship and no waves come, so aim can be practiced
+/// and odd physics tried out. Asteroids are put in by hand: 1, 2 and 3 pick a size,
+/// dragging with the left mouse button throws one from where the drag started, and
+/// dragging from one with the right button gives it a new velocity. T slows the game
+/// down, F and G toggle rapid fire and the spread shot, and C clears the field.
+/// The speed is the game's `TimeScale`, which the console can set in any mode
+pub struct SandboxPlugin;
+
+impl Plugin for SandboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Sandbox::default())
+            .insert_resource(TimeScale::default())
+            .add_system(apply_time_scale)
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(open_sandbox))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(sandbox_keys)
+                    .with_system(drag_asteroids)
+                    .with_system(draw_drag_line.after(drag_asteroids))
+                    .with_system(update_sandbox_hud.after(sandbox_keys)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(close_sandbox))
+            .add_console_command("timescale", "<scale>", timescale);
+    }
+}
+
+/// How the sandbox's tools are set
+#[derive(Resource)]
+pub struct Sandbox {
+    /// the size of the asteroids thrown
+    pub size: AsteroidSize,
+    drag: Option<Drag>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            size: AsteroidSize::Large,
+            drag: None,
+        }
+    }
+}
+
+/// a drag with the mouse in progress
+struct Drag {
+    /// where it started, in the arena
+    start: Vec2,
+    /// the asteroid being given a new velocity, or none to throw a new one
+    target: Option<Entity>,
+}
+
+fn open_sandbox(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    shapes: Res<ShapeCache>,
+    mut sandbox: ResMut<Sandbox>,
+) {
+    if *mode != GameMode::Sandbox {
+        return;
+    }
+    *sandbox = Sandbox::default();
+    commands
+        .spawn(shapes.bundle(ShapeKind::Debris, Transform::default()))
+        .insert(Visibility { is_visible: false })
+        .insert(DragLine);
+    commands
+        .spawn(TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 20.0,
+                    color: Color::GRAY,
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(20.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                ..default()
+            },
+            ..default()
+        })
+        .insert(SandboxHud);
+}
+
+/// the game goes back to normal speed, whatever the sandbox or the console left it at
+#[allow(clippy::type_complexity)]
+fn close_sandbox(
+    mut commands: Commands,
+    mut time_scale: ResMut<TimeScale>,
+    tools: Query<Entity, Or<(With<DragLine>, With<SandboxHud>)>>,
+) {
+    if time_scale.0 != 1.0 {
+        time_scale.0 = 1.0;
+    }
+    for entity in tools.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Scale the physics' steps by `TimeScale`, from however they were set up before it
+/// first changed, so going back to 1.0 leaves them as they were
+fn apply_time_scale(
+    time_scale: Res<TimeScale>,
+    mut config: ResMut<RapierConfiguration>,
+    mut normal: Local<Option<TimestepMode>>,
+) {
+    if !time_scale.is_changed() {
+        return;
+    }
+    let scale = time_scale.0;
+    config.timestep_mode = match *normal.get_or_insert(config.timestep_mode) {
+        TimestepMode::Fixed { dt, substeps } => TimestepMode::Fixed {
+            dt: dt * scale,
+            substeps,
+        },
+        TimestepMode::Variable {
+            max_dt,
+            time_scale,
+            substeps,
+        } => TimestepMode::Variable {
+            max_dt,
+            time_scale: time_scale * scale,
+            substeps,
+        },
+        TimestepMode::Interpolated {
+            dt,
+            time_scale,
+            substeps,
+        } => TimestepMode::Interpolated {
+            dt,
+            time_scale: time_scale * scale,
+            substeps,
+        },
+    };
+}
+
+/// Run the game slower or faster, in any mode, until it ends
+fn timescale(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+    console::playing(world)?;
+    let time_scale: f32 = console::arg(args, 0)?;
+    if !(time_scale > 0.0 && time_scale <= MAX_TIME_SCALE) {
+        return Err(CommandError::Failed(format!(
+            "has to be above 0 and at most {}",
+            MAX_TIME_SCALE
+        )));
+    }
+    world.resource_mut::<TimeScale>().0 = time_scale;
+    Ok(format!("time x{}", time_scale))
+}
+
+fn sandbox_keys(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    keyboard: Res<Input<KeyCode>>,
+    mut sandbox: ResMut<Sandbox>,
+    mut power_ups: ResMut<PowerUps>,
+    mut time_scale: ResMut<TimeScale>,
+    asteroids: Query<Entity, With<Asteroid>>,
+) {
+    if *mode != GameMode::Sandbox {
+        return;
+    }
+    for (key, size) in [
+        (KeyCode::Key1, AsteroidSize::Small),
+        (KeyCode::Key2, AsteroidSize::Medium),
+        (KeyCode::Key3, AsteroidSize::Large),
+    ] {
+        if keyboard.just_pressed(key) {
+            sandbox.size = size;
+        }
+    }
+    if keyboard.just_pressed(KeyCode::T) {
+        let current = TIME_SCALES
+            .iter()
+            .position(|scale| *scale == time_scale.0)
+            .unwrap_or_default();
+        time_scale.0 = TIME_SCALES[(current + 1) % TIME_SCALES.len()];
+    }
+    if keyboard.just_pressed(KeyCode::F) {
+        power_ups.rapid_fire = !power_ups.rapid_fire;
+    }
+    if keyboard.just_pressed(KeyCode::G) {
+        power_ups.spread = if power_ups.spread > 1 { 1 } else { SPREAD_SHOT };
+    }
+    if keyboard.just_pressed(KeyCode::C) {
+        for entity in asteroids.iter() {
+            pool::release(&mut commands, entity);
+        }
+    }
+}
+
+/// Throw a new asteroid, or give one a push, when a drag with the mouse ends
+#[allow(clippy::too_many_arguments)]
+fn drag_asteroids(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    windows: Res<Windows>,
+    arena: Res<Arena>,
+    keyboard: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut sandbox: ResMut<Sandbox>,
+    mut pool: ResMut<Pool>,
+    cameras: Query<&Camera>,
+    mut asteroids: Query<(Entity, &Asteroid, &Transform, &mut Velocity)>,
+) {
+    // ctrl+clicks pick entities for the debug overlay
+    if *mode != GameMode::Sandbox || keyboard.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
+    }
+    let Some(cursor) = cursor_in_arena(&windows, &arena, &cameras) else {
+        return;
+    };
+
+    if buttons.just_pressed(MouseButton::Left) {
+        sandbox.drag = Some(Drag {
+            start: cursor,
+            target: None,
+        });
+    } else if buttons.just_pressed(MouseButton::Right) {
+        // the asteroid under the cursor
+        let target = asteroids
+            .iter()
+            .filter(|(_, asteroid, transform, _)| {
+                let reach = radius(asteroid.0) * transform.scale.x;
+                transform.translation.truncate().distance(cursor) < reach
+            })
+            .min_by(|(.., a, _), (.., b, _)| {
+                let distance =
+                    |transform: &Transform| transform.translation.truncate().distance(cursor);
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|(entity, ..)| entity);
+        sandbox.drag = target.map(|target| Drag {
+            start: cursor,
+            target: Some(target),
+        });
+    }
+
+    let released =
+        buttons.just_released(MouseButton::Left) || buttons.just_released(MouseButton::Right);
+    if !released {
+        return;
+    }
+    let Some(drag) = sandbox.drag.take() else {
+        return;
+    };
+    let linvel = (cursor - drag.start) * DRAG_SPEED;
+    match drag.target {
+        Some(target) => {
+            if let Ok((.., mut velocity)) = asteroids.get_mut(target) {
+                velocity.linvel = linvel;
+            }
+        }
+        None => pool.launch_asteroid(
+            &mut commands,
+            sandbox.size,
+            Transform::from_translation(drag.start.extend(0.0)),
+            Velocity {
+                linvel,
+                angvel: ASTEROID_SPIN,
+            },
+        ),
+    }
+}
+
+/// show which way, and how hard, a drag will send the asteroid
+fn draw_drag_line(
+    windows: Res<Windows>,
+    arena: Res<Arena>,
+    sandbox: Res<Sandbox>,
+    cameras: Query<&Camera>,
+    mut lines: Query<(&mut Transform, &mut Visibility), With<DragLine>>,
+) {
+    let drag = sandbox
+        .drag
+        .as_ref()
+        .zip(cursor_in_arena(&windows, &arena, &cameras));
+    for (mut transform, mut visibility) in lines.iter_mut() {
+        let Some((drag, cursor)) = drag else {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+            continue;
+        };
+        let line = cursor - drag.start;
+        *transform = Transform {
+            translation: ((drag.start + cursor) / 2.0).extend(1.0),
+            rotation: Quat::from_rotation_z(line.y.atan2(line.x)),
+            scale: Vec3::new(line.length(), 1.0, 1.0),
+        };
+        visibility.is_visible = true;
+    }
+}
+
+fn update_sandbox_hud(
+    sandbox: Res<Sandbox>,
+    time_scale: Res<TimeScale>,
+    power_ups: Res<PowerUps>,
+    mut query: Query<&mut Text, With<SandboxHud>>,
+) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    let value = format!(
+        "[1-3] Size {:?}    [T] Time x{}    [F] Rapid fire {}    [G] Spread {}    \
+         [C] Clear    Drag: left throws, right pushes",
+        sandbox.size,
+        time_scale.0,
+        on_off(power_ups.rapid_fire),
+        on_off(power_ups.spread > 1)
+    );
+    for mut text in query.iter_mut() {
+        // only touch the text when it changed so it isn't laid out every frame
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
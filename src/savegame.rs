@@ -0,0 +1,448 @@
+use crate::asteroid::DeferredSpawns;
+use crate::components::*;
+use crate::difficulty::GameDifficulty;
+use crate::modes::{GameClock, Survival};
+use crate::pool::Pool;
+use crate::resources::{Cheated, GameMode, Level, PlayerState, Players, Rounds};
+use crate::settings::{Difficulty, Settings};
+use crate::ship::create_ship;
+use crate::storage;
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const SAVE_KEY: &str = "savegame.toml";
+/// Bumped whenever the format changes in a way older saves can't be read with.
+/// Those are ignored rather than half restored
+const SAVE_VERSION: u32 = 2;
+
+/// Saves the game in play, so it can be picked up again with Continue on the main
+/// menu. It is saved whenever the game is paused (so quitting from the pause menu
+/// keeps it) and when the window is closed mid-game, and thrown away on game over.
+/// The run goes on at the difficulty it was started on, whatever the settings say by
+/// then, with the asteroids still waiting to come in and (in survival) the time to
+/// the next one. UFOs, explosions and the like aren't kept, and the UFO's clock
+/// starts over. Daily challenges aren't saved, so a bad start can't be undone by
+/// going back to a save
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PendingRun::default())
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(save_run))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(restore_run))
+            .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(discard_run))
+            .add_system_to_stage(CoreStage::Last, save_on_exit);
+    }
+}
+
+/// a run picked to continue. The usual ship and wave aren't spawned while it is
+/// set, and the next frame of play puts the saved ones in instead
+#[derive(Resource, Default)]
+pub struct PendingRun(pub Option<SaveGame>);
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct SavedTransform {
+    x: f32,
+    y: f32,
+    /// radians around z
+    rotation: f32,
+}
+
+impl From<&Transform> for SavedTransform {
+    fn from(transform: &Transform) -> Self {
+        Self {
+            x: transform.translation.x,
+            y: transform.translation.y,
+            rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+        }
+    }
+}
+
+impl From<SavedTransform> for Transform {
+    fn from(saved: SavedTransform) -> Self {
+        Transform::from_xyz(saved.x, saved.y, 0.0)
+            .with_rotation(Quat::from_rotation_z(saved.rotation))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+struct SavedVelocity {
+    x: f32,
+    y: f32,
+    angular: f32,
+}
+
+impl From<&Velocity> for SavedVelocity {
+    fn from(velocity: &Velocity) -> Self {
+        Self {
+            x: velocity.linvel.x,
+            y: velocity.linvel.y,
+            angular: velocity.angvel,
+        }
+    }
+}
+
+impl From<SavedVelocity> for Velocity {
+    fn from(saved: SavedVelocity) -> Self {
+        Velocity {
+            linvel: Vec2::new(saved.x, saved.y),
+            angvel: saved.angular,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedPlayer {
+    score: u32,
+    lives: u32,
+    /// seconds until their ship comes back, while it is destroyed
+    respawn: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedShip {
+    player: usize,
+    /// seconds since it last fired
+    fire_timer: f32,
+    transform: SavedTransform,
+    velocity: SavedVelocity,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedAsteroid {
+    size: AsteroidSize,
+    transform: SavedTransform,
+    velocity: SavedVelocity,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct SavedLaser {
+    owner: usize,
+    transform: SavedTransform,
+    velocity: SavedVelocity,
+}
+
+/// Everything needed to pick a game up where it was left
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SaveGame {
+    version: u32,
+    pub mode: GameMode,
+    /// defaulted only so that an older save gets as far as the version check
+    #[serde(default)]
+    difficulty: Difficulty,
+    level: u32,
+    /// seconds on the game clock, for the modes played against it
+    #[serde(default)]
+    clock: f32,
+    /// versus only
+    #[serde(default)]
+    round: u32,
+    #[serde(default)]
+    wins: Vec<u32>,
+    /// the console was used, so the run stays out of the high scores
+    #[serde(default)]
+    cheated: bool,
+    /// asteroids there wasn't room for yet
+    #[serde(default)]
+    deferred: Vec<AsteroidSize>,
+    /// seconds until the next asteroid streams in, in survival
+    #[serde(default)]
+    next_asteroid: f32,
+    // the tables come after all the plain values, as TOML needs them to
+    players: Vec<SavedPlayer>,
+    ships: Vec<SavedShip>,
+    asteroids: Vec<SavedAsteroid>,
+    lasers: Vec<SavedLaser>,
+}
+
+impl SaveGame {
+    /// The saved run, if there is one this version can read
+    pub fn load() -> Option<Self> {
+        Self::parse(&storage::load(SAVE_KEY)?)
+    }
+
+    fn parse(contents: &str) -> Option<Self> {
+        match toml::from_str::<SaveGame>(contents) {
+            Ok(save) if save.version == SAVE_VERSION => Some(save),
+            Ok(save) => {
+                warn!("ignoring a saved game from version {}", save.version);
+                None
+            }
+            Err(error) => {
+                warn!("ignoring invalid saved game: {}", error);
+                None
+            }
+        }
+    }
+
+    fn store(&self) {
+        match toml::to_string(self) {
+            Ok(contents) => storage::save(SAVE_KEY, &contents),
+            Err(error) => warn!("could not serialize the game: {}", error),
+        }
+    }
+}
+
+/// whether there is a game to continue, for the main menu
+pub fn can_continue() -> bool {
+    SaveGame::load().is_some()
+}
+
+/// the game in play, as far as saving goes
+#[derive(SystemParam)]
+struct Run<'w, 's> {
+    mode: Res<'w, GameMode>,
+    difficulty: Res<'w, GameDifficulty>,
+    level: Res<'w, Level>,
+    clock: Res<'w, GameClock>,
+    players: Res<'w, Players>,
+    rounds: Res<'w, Rounds>,
+    cheated: Res<'w, Cheated>,
+    deferred: Res<'w, DeferredSpawns>,
+    survival: Res<'w, Survival>,
+    ships: Query<
+        'w,
+        's,
+        (
+            &'static Player,
+            &'static Transform,
+            &'static Velocity,
+            &'static FireTimer,
+        ),
+        With<Ship>,
+    >,
+    asteroids: Query<'w, 's, (&'static Asteroid, &'static Transform, &'static Velocity)>,
+    lasers: Query<'w, 's, (&'static Laser, &'static Transform, &'static Velocity)>,
+}
+
+impl<'w, 's> Run<'w, 's> {
+    fn snapshot(&self) -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            mode: *self.mode,
+            difficulty: self.difficulty.0,
+            level: self.level.0,
+            clock: self.clock.0.elapsed_secs(),
+            players: self
+                .players
+                .0
+                .iter()
+                .map(|player| SavedPlayer {
+                    score: player.score,
+                    lives: player.lives,
+                    respawn: player.respawn.as_ref().map(Timer::remaining_secs),
+                })
+                .collect(),
+            round: self.rounds.number,
+            wins: self.rounds.wins.clone(),
+            cheated: self.cheated.0,
+            deferred: self.deferred.0.clone(),
+            next_asteroid: self.survival.next.remaining_secs(),
+            ships: self
+                .ships
+                .iter()
+                .map(|(player, transform, velocity, fire_timer)| SavedShip {
+                    player: player.0,
+                    transform: transform.into(),
+                    velocity: velocity.into(),
+                    fire_timer: fire_timer.0.elapsed_secs(),
+                })
+                .collect(),
+            asteroids: self
+                .asteroids
+                .iter()
+                .map(|(asteroid, transform, velocity)| SavedAsteroid {
+                    size: asteroid.0,
+                    transform: transform.into(),
+                    velocity: velocity.into(),
+                })
+                .collect(),
+            lasers: self
+                .lasers
+                .iter()
+                .map(|(laser, transform, velocity)| SavedLaser {
+                    owner: laser.owner.0,
+                    transform: transform.into(),
+                    velocity: velocity.into(),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn save_run(run: Run) {
+    if *run.mode != GameMode::Daily {
+        run.snapshot().store();
+    }
+}
+
+/// closing the window in the middle of a game keeps it too
+fn save_on_exit(state: Res<State<GameState>>, mut exit_events: EventReader<AppExit>, run: Run) {
+    if exit_events.iter().count() == 0 {
+        return;
+    }
+    if matches!(state.current(), GameState::Playing | GameState::Paused)
+        && *run.mode != GameMode::Daily
+    {
+        run.snapshot().store();
+    }
+}
+
+fn discard_run() {
+    storage::remove(SAVE_KEY);
+}
+
+/// Swap the freshly started game for the saved one
+#[allow(clippy::too_many_arguments)]
+fn restore_run(
+    mut commands: Commands,
+    mut pending: ResMut<PendingRun>,
+    mut pool: ResMut<Pool>,
+    settings: Res<Settings>,
+    mut difficulty: ResMut<GameDifficulty>,
+    mut level: ResMut<Level>,
+    mut clock: ResMut<GameClock>,
+    mut players: ResMut<Players>,
+    mut rounds: ResMut<Rounds>,
+    mut cheated: ResMut<Cheated>,
+    mut deferred: ResMut<DeferredSpawns>,
+    mut survival: ResMut<Survival>,
+) {
+    let Some(save) = pending.0.take() else {
+        return;
+    };
+
+    difficulty.0 = save.difficulty;
+    level.0 = save.level;
+    cheated.0 = save.cheated;
+    deferred.0 = save.deferred.clone();
+    if save.mode == GameMode::Survival {
+        survival.next = Timer::from_seconds(save.next_asteroid, TimerMode::Once);
+    }
+    clock.0.set_elapsed(Duration::from_secs_f32(save.clock));
+    players.0 = save
+        .players
+        .iter()
+        .map(|player| PlayerState {
+            score: player.score,
+            lives: player.lives,
+            respawn: player
+                .respawn
+                .map(|seconds| Timer::from_seconds(seconds, TimerMode::Once)),
+        })
+        .collect();
+    if !save.wins.is_empty() {
+        *rounds = Rounds {
+            number: save.round,
+            wins: save.wins.clone(),
+        };
+    }
+
+    for ship in save.ships.iter() {
+        let transform = Transform::from(ship.transform);
+        let entity = create_ship(
+            &mut commands,
+            &settings,
+            difficulty.0,
+            Player(ship.player),
+            transform.translation.truncate(),
+        );
+        let mut fire_timer = FireTimer::default();
+        fire_timer
+            .0
+            .set_elapsed(Duration::from_secs_f32(ship.fire_timer));
+        commands
+            .entity(entity)
+            .insert(transform)
+            .insert(Velocity::from(ship.velocity))
+            .insert(fire_timer);
+    }
+    for asteroid in save.asteroids.iter() {
+        pool.launch_asteroid(
+            &mut commands,
+            asteroid.size,
+            asteroid.transform.into(),
+            asteroid.velocity.into(),
+        );
+    }
+    for laser in save.lasers.iter() {
+        let velocity = Velocity::from(laser.velocity);
+        pool.fire_laser(
+            &mut commands,
+            Player(laser.owner),
+            laser.transform.into(),
+            velocity.linvel,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+
+    /// a Hard survival run part way through, with one of everything that is saved
+    fn run() -> World {
+        let mut world = World::new();
+        world.insert_resource(GameMode::Survival);
+        world.insert_resource(GameDifficulty(Difficulty::Hard));
+        world.insert_resource(Level(4));
+        world.insert_resource(GameClock::default());
+        world.insert_resource(Players::new(1, 2));
+        world.insert_resource(Rounds::default());
+        world.insert_resource(Cheated(true));
+        world.insert_resource(DeferredSpawns(vec![AsteroidSize::Large]));
+        world.insert_resource(Survival::default());
+        world.spawn((
+            Ship,
+            Player(0),
+            Transform::from_xyz(10.0, -20.0, 0.0),
+            Velocity::linear(Vec2::new(1.0, 2.0)),
+            FireTimer::default(),
+        ));
+        world.spawn((
+            Asteroid(AsteroidSize::Medium),
+            Transform::from_xyz(-300.0, 150.0, 0.0),
+            Velocity::angular(0.4),
+        ));
+        world.spawn((
+            Laser { owner: Player(0) },
+            Transform::from_xyz(0.0, 50.0, 0.0),
+            Velocity::linear(Vec2::Y),
+        ));
+        world
+    }
+
+    fn snapshot(world: &mut World) -> SaveGame {
+        let mut state: SystemState<Run> = SystemState::new(world);
+        state.get(world).snapshot()
+    }
+
+    #[test]
+    fn a_save_reads_back_the_same() {
+        let save = snapshot(&mut run());
+        assert_eq!(save.difficulty, Difficulty::Hard);
+        assert_eq!(save.deferred, [AsteroidSize::Large]);
+        assert_eq!(
+            (save.ships.len(), save.asteroids.len(), save.lasers.len()),
+            (1, 1, 1)
+        );
+
+        let contents = toml::to_string(&save).unwrap();
+        assert_eq!(toml::from_str::<SaveGame>(&contents).unwrap(), save);
+        assert_eq!(SaveGame::parse(&contents), Some(save));
+    }
+
+    #[test]
+    fn another_version_is_not_loaded() {
+        let mut save = snapshot(&mut run());
+        save.version = SAVE_VERSION + 1;
+        assert_eq!(SaveGame::parse(&toml::to_string(&save).unwrap()), None);
+        save.version = SAVE_VERSION - 1;
+        assert_eq!(SaveGame::parse(&toml::to_string(&save).unwrap()), None);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::components::*;
+use crate::events::{AsteroidDestroyed, UfoDestroyed};
+use crate::resources::{GameMode, Players};
+use bevy::prelude::*;
+
+/// points for shooting down a UFO
+pub const UFO_POINTS: u32 = 200;
+
+/// Awards points to whoever shoots something down
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Playing).with_system(award_points));
+    }
+}
+
+/// classic scoring: the smaller the rock, the more it is worth
+pub fn points(size: AsteroidSize) -> u32 {
+    match size {
+        AsteroidSize::Large => 20,
+        AsteroidSize::Medium => 50,
+        AsteroidSize::Small => 100,
+    }
+}
+
+/// credit `by` with `points`, except in versus, which counts kills instead
+pub fn award(players: &mut Players, mode: &GameMode, by: Player, points: u32) {
+    if *mode == GameMode::Versus {
+        return;
+    }
+    if let Some(state) = players.get_mut(by) {
+        state.score += points;
+    }
+}
+
+fn award_points(
+    mode: Res<GameMode>,
+    mut players: ResMut<Players>,
+    mut asteroid_events: EventReader<AsteroidDestroyed>,
+    mut ufo_events: EventReader<UfoDestroyed>,
+) {
+    for destroyed in asteroid_events.iter() {
+        award(&mut players, &mode, destroyed.by, points(destroyed.size));
+    }
+    for destroyed in ufo_events.iter() {
+        award(&mut players, &mode, destroyed.by, UFO_POINTS);
+    }
+}
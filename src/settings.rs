@@ -0,0 +1,511 @@
+use crate::components::*;
+use crate::menu::{spawn_menu, MenuInput};
+use crate::resources::*;
+use crate::storage;
+use bevy::{prelude::*, window::WindowMode};
+use bevy_prototype_lyon::prelude::{FillMode, *};
+use serde::{Deserialize, Serialize};
+
+/// where the settings are persisted (a file natively, a localStorage key on the web)
+const SETTINGS_KEY: &str = "settings.toml";
+
+/// resolutions offered in the settings screen
+const RESOLUTIONS: [(f32, f32); 4] = [
+    (1280.0, 720.0),
+    (1600.0, 900.0),
+    (1920.0, 1080.0),
+    (1024.0, 768.0),
+];
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(apply_settings)
+            .add_system_set(SystemSet::on_enter(GameState::Settings).with_system(spawn_settings))
+            .add_system_set(build_update_system_set(GameState::Settings))
+            .add_system_set(SystemSet::on_exit(GameState::Settings).with_system(save_settings))
+            // the difficulty can also be picked right before starting a game
+            .add_system_set(build_update_system_set(GameState::ModeSelect))
+            .add_system_set(SystemSet::on_exit(GameState::ModeSelect).with_system(save_settings));
+    }
+}
+
+fn build_update_system_set(state: GameState) -> SystemSet {
+    SystemSet::on_update(state)
+        .with_system(adjust_settings)
+        .with_system(update_labels.after(adjust_settings))
+}
+
+/// Every user facing option. Missing keys in the file fall back to their defaults
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub audio: AudioSettings,
+    pub gameplay: GameplaySettings,
+    pub accessibility: AccessibilitySettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+    /// 1 (off) or 4
+    pub msaa: u32,
+    /// vector monitor look with glow, trails and scanlines
+    pub retro: bool,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            width: RESOLUTIONS[0].0,
+            height: RESOLUTIONS[0].1,
+            fullscreen: false,
+            msaa: 4,
+            retro: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// 0.0 to 1.0
+    pub volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { volume: 0.8 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct GameplaySettings {
+    pub controls: ControlScheme,
+    pub difficulty: Difficulty,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// pure black background with thicker outlines
+    pub high_contrast: bool,
+    /// scale all text up
+    pub large_text: bool,
+    /// no screen shake, freezes or zooming
+    pub reduce_motion: bool,
+}
+
+/// which keys fly the ship
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ControlScheme {
+    /// A/D to turn, W to thrust, space to fire
+    #[default]
+    Wasd,
+    /// left/right to turn, up to thrust, space to fire
+    Arrows,
+}
+
+/// the keys used by a control scheme
+pub struct ShipKeys {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub thrust: KeyCode,
+    pub fire: KeyCode,
+}
+
+impl ControlScheme {
+    pub fn keys(&self) -> ShipKeys {
+        match self {
+            ControlScheme::Wasd => ShipKeys {
+                left: KeyCode::A,
+                right: KeyCode::D,
+                thrust: KeyCode::W,
+                fire: KeyCode::Space,
+            },
+            ControlScheme::Arrows => ShipKeys {
+                left: KeyCode::Left,
+                right: KeyCode::Right,
+                thrust: KeyCode::Up,
+                fire: KeyCode::Space,
+            },
+        }
+    }
+
+    /// With two people on one keyboard the first gets WASD and the second the
+    /// arrows, and the second fires with enter so nobody shares a key
+    pub fn shared_keys(player: Player) -> ShipKeys {
+        match player.0 {
+            0 => ControlScheme::Wasd.keys(),
+            _ => ShipKeys {
+                fire: KeyCode::Return,
+                ..ControlScheme::Arrows.keys()
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Arcade,
+}
+
+/// the options that can be edited from the settings screen
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SettingField {
+    Resolution,
+    Fullscreen,
+    Msaa,
+    Retro,
+    Volume,
+    Controls,
+    Difficulty,
+    HighContrast,
+    LargeText,
+    ReduceMotion,
+}
+
+/// Whether the game was started with `--name`, or with `?name` in the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_flag(name: &str) -> bool {
+    std::env::args().any(|arg| arg.strip_prefix("--") == Some(name))
+}
+
+/// The value given with `--name value`, or with `?name=value` in the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub fn launch_value(name: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg.strip_prefix("--") == Some(name))?;
+    args.next()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn launch_value(name: &str) -> Option<String> {
+    let query = web_sys::window()?.location().search().ok()?;
+    query
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+        .map(str::to_string)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn launch_flag(name: &str) -> bool {
+    web_sys::window()
+        .and_then(|window| window.location().search().ok())
+        .is_some_and(|query| {
+            query
+                .trim_start_matches('?')
+                .split('&')
+                .any(|param| param == name)
+        })
+}
+
+impl Settings {
+    /// Read the saved settings, or the defaults if there are none (or they are broken)
+    pub fn load() -> Self {
+        storage::load(SETTINGS_KEY)
+            .and_then(|contents| match toml::from_str(&contents) {
+                Ok(settings) => Some(settings),
+                Err(error) => {
+                    warn!("ignoring invalid settings: {}", error);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match toml::to_string_pretty(self) {
+            Ok(contents) => storage::save(SETTINGS_KEY, &contents),
+            Err(error) => warn!("could not serialize settings: {}", error),
+        }
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.video.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+
+    pub fn fill_color(&self) -> Color {
+        if self.accessibility.high_contrast {
+            Color::BLACK
+        } else {
+            FILL_COLOR
+        }
+    }
+
+    /// The arena's background. In the retro display mode it is see-through, so each
+    /// frame only partly covers the previous ones and moving lines leave trails
+    pub fn background_color(&self) -> Color {
+        let mut fill = self.fill_color();
+        if self.video.retro {
+            fill.set_a(0.35);
+        }
+        fill
+    }
+
+    /// how every outlined shape in the game is drawn
+    pub fn draw_mode(&self) -> DrawMode {
+        let line_width = if self.accessibility.high_contrast {
+            2.5
+        } else {
+            1.0
+        };
+        DrawMode::Outlined {
+            fill_mode: FillMode {
+                color: self.fill_color(),
+                options: FillOptions::default(),
+            },
+            outline_mode: StrokeMode {
+                color: Color::WHITE,
+                options: StrokeOptions::default().with_line_width(line_width),
+            },
+        }
+    }
+
+    /// the ships are drawn like everything else, in each player's own color
+    pub fn ship_draw_mode(&self, player: Player) -> DrawMode {
+        match self.draw_mode() {
+            DrawMode::Outlined {
+                fill_mode,
+                mut outline_mode,
+            } => {
+                outline_mode.color = player.color();
+                DrawMode::Outlined {
+                    fill_mode,
+                    outline_mode,
+                }
+            }
+            draw_mode => draw_mode,
+        }
+    }
+
+    pub fn ui_scale(&self) -> f64 {
+        if self.accessibility.large_text {
+            1.25
+        } else {
+            1.0
+        }
+    }
+
+    /// Step a field forwards (or backwards) through its possible values
+    pub fn adjust(&mut self, field: SettingField, forward: bool) {
+        match field {
+            SettingField::Resolution => {
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|r| *r == (self.video.width, self.video.height))
+                    .unwrap_or(0);
+                let (width, height) = RESOLUTIONS[cycle(current, RESOLUTIONS.len(), forward)];
+                self.video.width = width;
+                self.video.height = height;
+            }
+            SettingField::Fullscreen => self.video.fullscreen = !self.video.fullscreen,
+            SettingField::Msaa => self.video.msaa = if self.video.msaa == 1 { 4 } else { 1 },
+            SettingField::Retro => self.video.retro = !self.video.retro,
+            SettingField::Volume => {
+                let step = if forward { 0.1 } else { -0.1 };
+                // round so repeated steps don't accumulate float error
+                self.audio.volume =
+                    ((self.audio.volume + step).clamp(0.0, 1.0) * 10.0).round() / 10.0;
+            }
+            SettingField::Controls => {
+                self.gameplay.controls = match self.gameplay.controls {
+                    ControlScheme::Wasd => ControlScheme::Arrows,
+                    ControlScheme::Arrows => ControlScheme::Wasd,
+                }
+            }
+            SettingField::Difficulty => {
+                let all = [
+                    Difficulty::Easy,
+                    Difficulty::Normal,
+                    Difficulty::Hard,
+                    Difficulty::Arcade,
+                ];
+                let current = all
+                    .iter()
+                    .position(|d| *d == self.gameplay.difficulty)
+                    .unwrap_or(0);
+                self.gameplay.difficulty = all[cycle(current, all.len(), forward)];
+            }
+            SettingField::HighContrast => {
+                self.accessibility.high_contrast = !self.accessibility.high_contrast
+            }
+            SettingField::LargeText => {
+                self.accessibility.large_text = !self.accessibility.large_text
+            }
+            SettingField::ReduceMotion => {
+                self.accessibility.reduce_motion = !self.accessibility.reduce_motion
+            }
+        }
+    }
+
+    /// the text shown for a field in the settings screen
+    pub fn label(&self, field: SettingField) -> String {
+        let on_off = |value: bool| if value { "On" } else { "Off" };
+        match field {
+            SettingField::Resolution => {
+                format!("Resolution: {}x{}", self.video.width, self.video.height)
+            }
+            SettingField::Fullscreen => format!("Fullscreen: {}", on_off(self.video.fullscreen)),
+            SettingField::Msaa => format!("Anti-aliasing: {}", on_off(self.video.msaa > 1)),
+            SettingField::Retro => format!("Retro display: {}", on_off(self.video.retro)),
+            SettingField::Volume => format!("Volume: {:.0}%", self.audio.volume * 100.0),
+            SettingField::Controls => format!("Controls: {:?}", self.gameplay.controls),
+            SettingField::Difficulty => format!("Difficulty: {:?}", self.gameplay.difficulty),
+            SettingField::HighContrast => {
+                format!(
+                    "High contrast: {}",
+                    on_off(self.accessibility.high_contrast)
+                )
+            }
+            SettingField::LargeText => {
+                format!("Large text: {}", on_off(self.accessibility.large_text))
+            }
+            SettingField::ReduceMotion => {
+                format!(
+                    "Reduce motion: {}",
+                    on_off(self.accessibility.reduce_motion)
+                )
+            }
+        }
+    }
+}
+
+fn cycle(index: usize, len: usize, forward: bool) -> usize {
+    if forward {
+        (index + 1) % len
+    } else {
+        (index + len - 1) % len
+    }
+}
+
+/// Push any changed settings out to the window, renderer and existing shapes
+fn apply_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    mut msaa: ResMut<Msaa>,
+    mut background: Query<&mut Sprite, With<ArenaBackground>>,
+    mut ui_scale: ResMut<UiScale>,
+    mut shapes: Query<&mut DrawMode, (With<Path>, Without<Player>)>,
+    mut ships: Query<(&Player, &mut DrawMode), With<Path>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    // the browser canvas is sized by the page, not by us
+    if cfg!(not(target_arch = "wasm32")) {
+        if let Some(window) = windows.get_primary_mut() {
+            if window.mode() != settings.window_mode() {
+                window.set_mode(settings.window_mode());
+            }
+            if window.requested_width() != settings.video.width
+                || window.requested_height() != settings.video.height
+            {
+                window.set_resolution(settings.video.width, settings.video.height);
+            }
+        }
+    }
+
+    if msaa.samples != settings.video.msaa {
+        msaa.samples = settings.video.msaa;
+    }
+    for mut sprite in background.iter_mut() {
+        sprite.color = settings.background_color();
+    }
+    ui_scale.scale = settings.ui_scale();
+
+    let draw_mode = settings.draw_mode();
+    for mut mode in shapes.iter_mut() {
+        *mode = draw_mode;
+    }
+    for (player, mut mode) in ships.iter_mut() {
+        *mode = settings.ship_draw_mode(*player);
+    }
+}
+
+fn spawn_settings(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut cursor: ResMut<MenuCursor>,
+    settings: Res<Settings>,
+) {
+    cursor.0 = 0;
+    let mut fields = vec![
+        SettingField::Msaa,
+        SettingField::Retro,
+        SettingField::Volume,
+        SettingField::Controls,
+        SettingField::Difficulty,
+        SettingField::HighContrast,
+        SettingField::LargeText,
+        SettingField::ReduceMotion,
+    ];
+    if cfg!(not(target_arch = "wasm32")) {
+        fields.splice(0..0, [SettingField::Resolution, SettingField::Fullscreen]);
+    }
+
+    let mut items: Vec<(String, MenuAction)> = fields
+        .into_iter()
+        .map(|field| (settings.label(field), MenuAction::Adjust(field)))
+        .collect();
+    items.push(("Back".to_string(), MenuAction::Goto(GameState::MainMenu)));
+    let items: Vec<(&str, MenuAction)> = items
+        .iter()
+        .map(|(label, action)| (label.as_str(), *action))
+        .collect();
+
+    spawn_menu(&mut commands, &asset_server, "SETTINGS", &[], &items);
+}
+
+/// left/right (or confirm) on a setting changes its value
+fn adjust_settings(
+    input: MenuInput,
+    cursor: Res<MenuCursor>,
+    mut settings: ResMut<Settings>,
+    items: Query<&MenuItem>,
+) {
+    let forward = if input.right() || input.confirm() {
+        true
+    } else if input.left() {
+        false
+    } else {
+        return;
+    };
+
+    for item in items.iter() {
+        if let (true, MenuAction::Adjust(field)) = (item.index == cursor.0, item.action) {
+            settings.adjust(field, forward);
+        }
+    }
+}
+
+fn update_labels(settings: Res<Settings>, mut items: Query<(&MenuItem, &mut Text)>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (item, mut text) in items.iter_mut() {
+        if let MenuAction::Adjust(field) = item.action {
+            text.sections[0].value = settings.label(field);
+        }
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    settings.save();
+}
@@ -0,0 +1,186 @@
+use crate::asteroid::{create_shape, radius};
+use crate::components::AsteroidSize;
+use crate::particles::FLAME_POINTS;
+use crate::settings::Settings;
+use crate::ship::LASER_POINTS;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::sprite::Mesh2dHandle;
+use bevy::utils::HashMap;
+use bevy_prototype_lyon::geometry::Geometry;
+use bevy_prototype_lyon::prelude::*;
+use bevy_prototype_lyon::render::Shape;
+
+/// Every asteroid of a size looks the same, as does every laser and piece of debris.
+/// Their shapes are tessellated once into meshes all of them share, instead of once
+/// per entity like lyon does for a `Path`. When the settings change the way shapes
+/// are drawn the shared meshes are rebuilt in place
+pub struct ShapeCachePlugin;
+
+impl Plugin for ShapeCachePlugin {
+    fn build(&self, app: &mut App) {
+        // before the pools are filled at startup
+        app.add_startup_system_to_stage(StartupStage::PreStartup, build_cache)
+            .add_system(rebuild_meshes);
+    }
+}
+
+/// the shapes that are shared
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ShapeKind {
+    Laser,
+    Asteroid(AsteroidSize),
+    /// a line one unit long, stretched to size with the transform's scale
+    Debris,
+    /// the flame behind the ship
+    Flame,
+    /// the UFO's shot
+    Bullet,
+}
+
+impl ShapeKind {
+    const ALL: [ShapeKind; 7] = [
+        ShapeKind::Laser,
+        ShapeKind::Asteroid(AsteroidSize::Large),
+        ShapeKind::Asteroid(AsteroidSize::Medium),
+        ShapeKind::Asteroid(AsteroidSize::Small),
+        ShapeKind::Debris,
+        ShapeKind::Flame,
+        ShapeKind::Bullet,
+    ];
+
+    fn path(self) -> tess::path::Path {
+        let mut builder = tess::path::Path::builder();
+        match self {
+            ShapeKind::Laser => shapes::Polygon {
+                points: LASER_POINTS.to_vec(),
+                closed: true,
+            }
+            .add_geometry(&mut builder),
+            ShapeKind::Asteroid(size) => create_shape(radius(size)).add_geometry(&mut builder),
+            ShapeKind::Debris => {
+                shapes::Line(Vec2::new(-0.5, 0.0), Vec2::new(0.5, 0.0)).add_geometry(&mut builder)
+            }
+            ShapeKind::Flame => shapes::Polygon {
+                points: FLAME_POINTS.to_vec(),
+                closed: false,
+            }
+            .add_geometry(&mut builder),
+            ShapeKind::Bullet => shapes::Rectangle {
+                extents: Vec2::new(3.0, 3.0),
+                origin: RectangleOrigin::Center,
+            }
+            .add_geometry(&mut builder),
+        }
+        builder.build()
+    }
+}
+
+/// The mesh of every shared shape
+#[derive(Resource)]
+pub struct ShapeCache {
+    meshes: HashMap<ShapeKind, Handle<Mesh>>,
+}
+
+impl ShapeCache {
+    /// What an entity needs to be drawn as `kind`; the same as a lyon `ShapeBundle`
+    /// minus the path, so lyon leaves it alone
+    pub fn bundle(
+        &self,
+        kind: ShapeKind,
+        transform: Transform,
+    ) -> (SpatialBundle, Shape, Mesh2dHandle) {
+        (
+            SpatialBundle::from_transform(transform),
+            Shape,
+            Mesh2dHandle(self.meshes[&kind].clone()),
+        )
+    }
+}
+
+fn build_cache(mut commands: Commands, settings: Res<Settings>, mut meshes: ResMut<Assets<Mesh>>) {
+    let draw_mode = settings.draw_mode();
+    let meshes = ShapeKind::ALL
+        .into_iter()
+        .map(|kind| (kind, meshes.add(tessellate(&kind.path(), &draw_mode))))
+        .collect();
+    commands.insert_resource(ShapeCache { meshes });
+}
+
+/// the colors and line width come from the settings and are part of the meshes
+fn rebuild_meshes(
+    settings: Res<Settings>,
+    cache: Res<ShapeCache>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    let draw_mode = settings.draw_mode();
+    for (kind, handle) in cache.meshes.iter() {
+        if let Some(mesh) = meshes.get_mut(handle) {
+            *mesh = tessellate(&kind.path(), &draw_mode);
+        }
+    }
+}
+
+/// position and color, which is all lyon's shape shader needs
+type Vertex = ([f32; 3], [f32; 4]);
+
+/// gives every vertex the same color
+struct Paint([f32; 4]);
+
+impl tess::FillVertexConstructor<Vertex> for Paint {
+    fn new_vertex(&mut self, vertex: tess::FillVertex) -> Vertex {
+        let position = vertex.position();
+        ([position.x, position.y, 0.0], self.0)
+    }
+}
+
+impl tess::StrokeVertexConstructor<Vertex> for Paint {
+    fn new_vertex(&mut self, vertex: tess::StrokeVertex) -> Vertex {
+        let position = vertex.position();
+        ([position.x, position.y, 0.0], self.0)
+    }
+}
+
+/// Turn a path into a mesh the same way lyon's own plugin does
+fn tessellate(path: &tess::path::Path, draw_mode: &DrawMode) -> Mesh {
+    let (fill_mode, stroke_mode) = match draw_mode {
+        DrawMode::Fill(fill_mode) => (Some(fill_mode), None),
+        DrawMode::Stroke(stroke_mode) => (None, Some(stroke_mode)),
+        DrawMode::Outlined {
+            fill_mode,
+            outline_mode,
+        } => (Some(fill_mode), Some(outline_mode)),
+    };
+
+    let mut buffers = tess::VertexBuffers::<Vertex, u32>::new();
+    if let Some(mode) = fill_mode {
+        let paint = Paint(mode.color.as_linear_rgba_f32());
+        if let Err(error) = tess::FillTessellator::new().tessellate_path(
+            path,
+            &mode.options,
+            &mut tess::BuffersBuilder::new(&mut buffers, paint),
+        ) {
+            error!("could not fill shape: {:?}", error);
+        }
+    }
+    if let Some(mode) = stroke_mode {
+        let paint = Paint(mode.color.as_linear_rgba_f32());
+        if let Err(error) = tess::StrokeTessellator::new().tessellate_path(
+            path,
+            &mode.options,
+            &mut tess::BuffersBuilder::new(&mut buffers, paint),
+        ) {
+            error!("could not stroke shape: {:?}", error);
+        }
+    }
+
+    let (positions, colors): (Vec<_>, Vec<_>) = buffers.vertices.into_iter().unzip();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(buffers.indices)));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh
+}
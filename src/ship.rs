@@ -1,19 +1,32 @@
 use crate::{
+    asteroid::SHIP_CLEARANCE,
+    collision::{DispatchCollisions, ShipHit},
     components::*,
-    resources::{FireTimer, FILL_COLOR},
+    console::{AddConsoleCommand, CommandError},
+    daily::{DailyRun, Modifier},
+    difficulty::GameDifficulty,
+    events::{LaserFired, ShipDestroyed},
+    pool::{self, Pool},
+    resources::{Arena, GameMode, GameTime, Players, PowerUps},
+    savegame::PendingRun,
+    settings::{ControlScheme, Difficulty, Settings},
 };
 use bevy::prelude::*;
-use bevy_prototype_lyon::prelude::{FillMode, *};
+use bevy_prototype_lyon::prelude::*;
 use bevy_rapier2d::prelude::*;
 
 pub struct ShipPlugin;
 
 impl Plugin for ShipPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(build_enter_system_set(GameState::GameOver))
+        // the title screen has the autopilot fly a ship around behind the menu as a demo
+        app.add_system_set(build_enter_system_set(GameState::Title))
             .add_system_set(build_enter_system_set(GameState::Playing))
-            .add_system_set(build_update_system_set(GameState::GameOver))
-            .add_system_set(build_update_system_set(GameState::Playing));
+            .add_system_set(build_update_system_set(GameState::Title))
+            .add_system_set(build_update_system_set(GameState::Playing))
+            .add_system_set(build_exit_system_set(GameState::Title))
+            .add_system_set(build_exit_system_set(GameState::Playing))
+            .add_console_command("weapon", "<normal|spread|rapid>", weapon);
     }
 }
 
@@ -21,225 +34,403 @@ fn build_enter_system_set(state: GameState) -> SystemSet {
     SystemSet::on_enter(state).with_system(spawn_ship)
 }
 
+fn build_exit_system_set(state: GameState) -> SystemSet {
+    SystemSet::on_exit(state)
+        .with_system(remove_ship)
+        .with_system(remove_lasers)
+}
+
 fn build_update_system_set(state: GameState) -> SystemSet {
     let system_set = SystemSet::on_update(state)
-        .with_system(ship_rotate_input)
-        .with_system(ship_move_forward);
+        .with_system(read_keyboard.label(ShipControl))
+        .with_system(ship_rotate_input.after(ShipControl))
+        .with_system(ship_move_forward.after(ShipControl))
+        .with_system(spawn_laser.after(ShipControl))
+        .with_system(destroy_laser)
+        .with_system(destroy_ship.after(DispatchCollisions));
     if state != GameState::Playing {
         return system_set;
     }
-    return system_set
-        .with_system(check_for_collision)
-        .with_system(spawn_laser)
-        .with_system(destroy_laser);
+    system_set
+        .with_system(lose_life.after(destroy_ship))
+        .with_system(respawn_ship)
 }
 
-fn spawn_ship(mut commands: Commands, mut query: Query<Entity, With<Ship>>) {
+/// Everything that fills in the ships' `ShipInput` has this label, and everything
+/// that acts on it runs after
+#[derive(SystemLabel)]
+pub struct ShipControl;
+
+/// how far the ship turns in a frame, in radians
+pub const TURN_SPEED: f32 = 0.1;
+pub const LASER_SPEED: f32 = 1000.0;
+
+/// the corners of a laser's rectangle, pointing up
+pub const LASER_POINTS: [Vec2; 4] = [
+    Vec2::new(-1.0, -5.0),
+    Vec2::new(1.0, -5.0),
+    Vec2::new(1.0, 5.0),
+    Vec2::new(-1.0, 5.0),
+];
+
+/// radians between the lasers of a spread shot
+const SPREAD_ANGLE: f32 = 0.15;
+/// lasers in a spread shot
+pub const SPREAD_SHOT: u32 = 3;
+
+/// how far apart the ships start when there are several
+const SHIP_SPACING: f32 = 200.0;
+pub const SHIP_WIDTH: f32 = 30.0;
+/// also how far ahead of the ship's center its lasers appear
+pub const SHIP_HEIGHT: f32 = 45.0;
+/// lasers are 10 units long; they go once they are completely out of the arena
+pub const LASER_MARGIN: f32 = 10.0;
+
+/// the corners of the ship's triangle, nose up
+pub const SHIP_POINTS: [Vec2; 3] = [
+    Vec2::new(-15.0, -22.5),
+    Vec2::new(0.0, 22.5),
+    Vec2::new(15.0, -22.5),
+];
+
+/// one ship for everyone playing, side by side
+fn spawn_ship(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    difficulty: Res<GameDifficulty>,
+    state: Res<State<GameState>>,
+    mode: Res<GameMode>,
+    pending: Res<PendingRun>,
+    mut query: Query<Entity, With<Ship>>,
+) {
     // Remove ship if it already eists
     for entity in query.iter_mut() {
-        commands.entity(entity).despawn();
+        commands.entity(entity).despawn_recursive();
     }
+    // a continued game brings its own
+    if pending.0.is_some() {
+        return;
+    }
+
+    // the demo on the title screen always has the one ship
+    let count = if *state.current() == GameState::Playing {
+        mode.players()
+    } else {
+        1
+    };
+    for id in 0..count {
+        let player = Player(id);
+        create_ship(
+            &mut commands,
+            &settings,
+            difficulty.0,
+            player,
+            start_position(player, count),
+        );
+    }
+}
+
+/// whether a ship can come back at `start` without an asteroid about to run into it
+pub fn respawn_clear(start: Vec2, asteroids: impl IntoIterator<Item = Vec2>) -> bool {
+    asteroids
+        .into_iter()
+        .all(|asteroid| asteroid.distance(start) > SHIP_CLEARANCE)
+}
+
+/// where `player`'s ship starts and comes back, in a row across the middle
+pub fn start_position(player: Player, count: usize) -> Vec2 {
+    let offset = player.0 as f32 - (count - 1) as f32 / 2.0;
+    Vec2::new(offset * SHIP_SPACING, 0.0)
+}
 
-    let shape_points = [
-        Vec2::new(-15.0, -22.5),
-        Vec2::new(0.0, 22.5),
-        Vec2::new(15.0, -22.5),
-    ];
+pub fn create_ship(
+    commands: &mut Commands,
+    settings: &Settings,
+    difficulty: Difficulty,
+    player: Player,
+    position: Vec2,
+) -> Entity {
+    let shape_points = SHIP_POINTS;
 
     // This is the actual ship graphics
     let triangle = shapes::Polygon {
         points: shape_points.to_vec(),
         closed: true,
-        ..Default::default()
     };
 
     commands
         .spawn(GeometryBuilder::build_as(
             &triangle,
-            DrawMode::Outlined {
-                fill_mode: FillMode {
-                    color: FILL_COLOR,
-                    options: FillOptions::default(),
-                },
-                outline_mode: StrokeMode {
-                    color: Color::WHITE,
-                    options: StrokeOptions::default(),
-                },
-            },
-            Transform::default(),
+            settings.ship_draw_mode(player),
+            Transform::from_translation(position.extend(0.0)),
         ))
         .insert(RigidBody::Dynamic)
         .insert(GravityScale(0.0))
-        .insert(Collider::triangle(
-            shape_points[0],
-            shape_points[1],
-            shape_points[2],
-        ))
+        .insert(ship_collider())
         .insert(ActiveEvents::COLLISION_EVENTS)
         .insert(Sensor)
         .insert(ExternalImpulse {
             impulse: Vec2::new(0.0, 0.0),
             ..default()
         })
+        // not set directly, but read when the ship explodes
+        .insert(Velocity::default())
         .insert(Damping {
-            linear_damping: 0.5,
+            linear_damping: difficulty.preset().damping,
             ..default()
         })
         .insert(Sleeping::disabled())
+        .insert(ShipInput::default())
+        .insert(FireTimer::ready(difficulty.preset().fire_cooldown))
         // .insert(LockedAxes::TRANSLATION_LOCKED)
         .insert(Ccd::enabled())
         .insert(Wrapper)
         .insert(Dimensions {
-            width: 30.0,
-            height: 45.0,
+            width: SHIP_WIDTH,
+            height: SHIP_HEIGHT,
         })
-        .insert(Ship);
+        .insert(player)
+        .insert(Ship)
+        .id()
+}
+
+pub fn ship_collider() -> Collider {
+    Collider::triangle(SHIP_POINTS[0], SHIP_POINTS[1], SHIP_POINTS[2])
+}
+
+pub fn laser_collider() -> Collider {
+    Collider::polyline(LASER_POINTS.to_vec(), None)
+}
+
+/// what rapier makes of the ship's triangle, to turn the thrust impulse into a
+/// change of speed
+pub fn ship_mass() -> f32 {
+    ship_collider().raw.mass_properties(1.0).mass()
+}
+
+fn remove_ship(mut commands: Commands, query: Query<Entity, With<Ship>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn remove_lasers(mut commands: Commands, query: Query<Entity, With<Laser>>) {
+    for entity in query.iter() {
+        pool::release(&mut commands, entity);
+    }
 }
 
-/** Updaters */
+/* Updaters */
 
-fn ship_rotate_input(
+/// each player's keys, for every ship not flown by the autopilot. Playing alone
+/// uses the keys picked in the settings
+fn read_keyboard(
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Ship>>,
+    settings: Res<Settings>,
+    mode: Res<GameMode>,
+    mut query: Query<(&Player, &mut ShipInput), Without<Autopilot>>,
 ) {
-    for mut transform in query.iter_mut() {
-        let mut rotation = 0.0;
-        if keyboard_input.pressed(KeyCode::A) {
-            rotation += 0.1;
-        }
-        if keyboard_input.pressed(KeyCode::D) {
-            rotation -= 0.1;
-        }
+    for (player, mut input) in query.iter_mut() {
+        let keys = if mode.players() == 1 {
+            settings.gameplay.controls.keys()
+        } else {
+            ControlScheme::shared_keys(*player)
+        };
+        *input = ShipInput {
+            left: keyboard_input.pressed(keys.left),
+            right: keyboard_input.pressed(keys.right),
+            thrust: keyboard_input.pressed(keys.thrust),
+            fire: keyboard_input.pressed(keys.fire),
+        };
+    }
+}
 
-        // Apply rotation and save angle to resource
-        transform.rotate(Quat::from_rotation_z(rotation));
+fn ship_rotate_input(time: GameTime, mut query: Query<(&ShipInput, &mut Transform), With<Ship>>) {
+    // turning goes by the frame, so slowing the game down has to slow it too
+    let turn = TURN_SPEED * time.scale();
+    for (input, mut transform) in query.iter_mut() {
+        transform.rotate(Quat::from_rotation_z(rotation(input, turn)));
+    }
+}
+
+/// how far `input` turns the ship, counterclockwise, when a turn is `turn` radians
+pub fn rotation(input: &ShipInput, turn: f32) -> f32 {
+    let mut rotation = 0.0;
+    if input.left {
+        rotation += turn;
+    }
+    if input.right {
+        rotation -= turn;
     }
+    rotation
+}
+
+/// The unit vector `angle` radians counterclockwise from the x axis. The trig is
+/// libm's rather than the platform's, so the online game works it out the same on
+/// both sides
+pub fn heading(angle: f32) -> Vec2 {
+    let (sin, cos) = libm::sincosf(angle);
+    Vec2::new(cos, sin)
 }
 
 fn ship_move_forward(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&mut ExternalImpulse, &mut Transform), With<Ship>>,
+    difficulty: Res<GameDifficulty>,
+    daily: Res<DailyRun>,
+    time: GameTime,
+    mut query: Query<(&ShipInput, &mut ExternalImpulse, &Transform), With<Ship>>,
 ) {
-    for (mut ext_impulse, transform) in query.iter_mut() {
-        if keyboard_input.pressed(KeyCode::W) {
+    if daily.has(Modifier::NoThrust) {
+        return;
+    }
+    // an impulse a frame, which the slowed physics would otherwise take in full
+    let thrust = difficulty.preset().thrust * time.scale();
+    for (input, mut ext_impulse, transform) in query.iter_mut() {
+        if input.thrust {
             // Add velocity in the direction the ship is facing
             let up = transform.up();
-            ext_impulse.impulse = Vec2::new(up.x, up.y) * 10000.0;
+            ext_impulse.impulse = Vec2::new(up.x, up.y) * thrust;
         }
     }
 }
 
-/// Create a laser entity and send it in the direction the ship is facing
+/// Take a laser from the pool and send it in the direction the ship is facing, or a
+/// fan of them with a spread shot
+#[allow(clippy::too_many_arguments)]
 fn spawn_laser(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
-    time: Res<Time>,
-    mut fire_timer: ResMut<FireTimer>,
-    mut query: Query<(&Transform, &Dimensions), With<Ship>>,
+    difficulty: Res<GameDifficulty>,
+    daily: Res<DailyRun>,
+    power_ups: Res<PowerUps>,
+    time: GameTime,
+    mut pool: ResMut<Pool>,
+    mut query: Query<(&Player, &ShipInput, &Transform, &Dimensions, &mut FireTimer), With<Ship>>,
+    mut fired_events: EventWriter<LaserFired>,
 ) {
-    fire_timer.0.tick(time.delta());
-    if !keyboard_input.pressed(KeyCode::Space) {
-        return;
+    let mut cooldown = difficulty.preset().fire_cooldown;
+    if daily.has(Modifier::RapidFire) || power_ups.rapid_fire {
+        cooldown /= 2.0;
     }
-    if !fire_timer.0.paused() && fire_timer.0.elapsed().as_secs_f32() < 0.2 {
-        return;
-    }
-
-    for (transform, dimensions) in query.iter_mut() {
-        let up = transform.up();
-        let position = transform.translation + up * dimensions.height;
-
-        let shape_points = [
-            Vec2::new(-1.0, -5.0),
-            Vec2::new(1.0, -5.0),
-            Vec2::new(1.0, 5.0),
-            Vec2::new(-1.0, 5.0),
-        ];
-
-        // This is the actual ship graphics
-        let rectangle = shapes::Polygon {
-            points: shape_points.to_vec(),
-            closed: true,
-            ..Default::default()
-        };
-
-        commands
-            .spawn(GeometryBuilder::build_as(
-                &rectangle,
-                DrawMode::Outlined {
-                    fill_mode: FillMode {
-                        color: FILL_COLOR,
-                        options: FillOptions::default(),
-                    },
-                    outline_mode: StrokeMode {
-                        color: Color::WHITE,
-                        options: StrokeOptions::default(),
-                    },
-                },
+    let spread = power_ups.spread.max(1);
+    for (player, input, transform, dimensions, mut fire_timer) in query.iter_mut() {
+        if !fire_timer.fire(time.delta(), cooldown, input.fire) {
+            continue;
+        }
+        for index in 0..spread {
+            let angle = (index as f32 - (spread - 1) as f32 / 2.0) * SPREAD_ANGLE;
+            let rotation = transform.rotation * Quat::from_rotation_z(angle);
+            let up = rotation * Vec3::Y;
+            let position = transform.translation + up * dimensions.height;
+            pool.fire_laser(
+                &mut commands,
+                *player,
                 Transform {
                     translation: position,
-                    rotation: transform.rotation,
-                    ..Default::default()
+                    rotation,
+                    ..default()
                 },
-            ))
-            .insert(RigidBody::Dynamic)
-            .insert(GravityScale(0.0))
-            .insert(Collider::polyline(shape_points.to_vec(), None))
-            .insert(ActiveEvents::COLLISION_EVENTS)
-            .insert(Sensor)
-            .insert(Velocity {
-                linvel: Vec2::new(up.x, up.y) * 1000.0,
-                ..default()
-            })
-            .insert(Ccd::enabled())
-            .insert(Sleeping::disabled())
-            .insert(Laser);
+                up.truncate() * LASER_SPEED,
+            );
+        }
+        fired_events.send(LaserFired);
     }
-
-    fire_timer.0.reset();
 }
 
-/// remove laser when if goes off screen
+/// remove laser when it leaves the arena
 fn destroy_laser(
     mut commands: Commands,
-    windows: Res<Windows>,
-    query: Query<(Entity, &Transform, &Dimensions), With<Laser>>,
+    arena: Res<Arena>,
+    query: Query<(Entity, &Transform), With<Laser>>,
 ) {
-    let window = windows.get_primary().unwrap();
-    let window_width = window.width() as f32;
-    let window_height = window.height() as f32;
-
-    for (entity, transform, dimensions) in query.iter() {
+    let bounds = arena.half_extents() + LASER_MARGIN;
+    for (entity, transform) in query.iter() {
         let position = transform.translation;
-        if position.x < -dimensions.width
-            || position.x > window_width + dimensions.width
-            || position.y < -dimensions.height
-            || position.y > window_height + dimensions.height
-        {
-            commands.entity(entity).despawn();
+        if position.x.abs() > bounds.x || position.y.abs() > bounds.y {
+            pool::release(&mut commands, entity);
+        }
+    }
+}
+
+/// the ship ran into an asteroid, a UFO or its lasers, or was shot down
+pub fn destroy_ship(
+    mut commands: Commands,
+    mut hits: EventReader<ShipHit>,
+    ship: Query<(&Player, &Transform, &Velocity), With<Ship>>,
+    mut destroyed_events: EventWriter<ShipDestroyed>,
+) {
+    for hit in hits.iter() {
+        let Ok((player, transform, velocity)) = ship.get(hit.ship) else {
+            continue;
+        };
+        commands.entity(hit.ship).despawn_recursive();
+        if let Some(laser) = hit.laser {
+            pool::release(&mut commands, laser);
         }
+        destroyed_events.send(ShipDestroyed {
+            player: *player,
+            by: hit.by,
+            position: transform.translation.truncate(),
+            velocity: velocity.linvel,
+            rotation: transform.rotation,
+        });
     }
 }
 
-// Check if the ship is making contact with an asteroid
-fn check_for_collision(
-    mut ship: Query<Entity, With<Ship>>,
-    asteroids: Query<Entity, With<Asteroid>>,
+/// a destroyed ship costs its player a life, and the game is over once nobody has
+/// one left. In versus nobody runs out; ships just come back
+pub fn lose_life(
+    mode: Res<GameMode>,
+    mut players: ResMut<Players>,
     mut state: ResMut<State<GameState>>,
-    mut collision_events: EventReader<CollisionEvent>,
+    mut destroyed_events: EventReader<ShipDestroyed>,
+) {
+    let mut lost = false;
+    for destroyed in destroyed_events.iter() {
+        lost |= players.ship_lost(destroyed.player, &mode);
+    }
+    if lost && players.all_out() {
+        state.set(GameState::GameOver).unwrap();
+    }
+}
+
+/// bring a ship back where it started a moment after it was destroyed, once no
+/// asteroid is about to run into it there
+pub fn respawn_ship(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    difficulty: Res<GameDifficulty>,
+    time: GameTime,
+    mut players: ResMut<Players>,
+    asteroids: Query<&Transform, With<Asteroid>>,
 ) {
-    for event in collision_events.iter() {
-        if let CollisionEvent::Started(h1, h2, _flags) = event {
-            for player in ship.iter_mut() {
-                for asteroid in asteroids.iter() {
-                    if (h1.to_owned() == player && h2.to_owned() == asteroid)
-                        || (h1.to_owned() == asteroid && h2.to_owned() == player)
-                    {
-                        // the game is over
-                        state.set(GameState::GameOver).unwrap();
-                    }
-                }
-            }
+    let count = players.0.len();
+    for (id, state) in players.0.iter_mut().enumerate() {
+        let player = Player(id);
+        let position = start_position(player, count);
+        let clear = respawn_clear(
+            position,
+            asteroids
+                .iter()
+                .map(|transform| transform.translation.truncate()),
+        );
+        if state.respawn_due(time.delta(), clear) {
+            create_ship(&mut commands, &settings, difficulty.0, player, position);
         }
     }
 }
+
+/// Toggle the spread shot or rapid fire, or go back to the plain gun
+fn weapon(world: &mut World, args: &[&str]) -> Result<String, CommandError> {
+    let mut power_ups = world.resource_mut::<PowerUps>();
+    match args {
+        ["normal"] => *power_ups = PowerUps::default(),
+        ["spread"] => power_ups.spread = if power_ups.spread > 1 { 1 } else { SPREAD_SHOT },
+        ["rapid"] => power_ups.rapid_fire = !power_ups.rapid_fire,
+        _ => return Err(CommandError::Usage),
+    }
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    Ok(format!(
+        "spread shot {}, rapid fire {}",
+        on_off(power_ups.spread > 1),
+        on_off(power_ups.rapid_fire)
+    ))
+}
@@ -0,0 +1,351 @@
+use crate::components::*;
+use crate::events::{AsteroidDestroyed, LaserFired, LevelCleared, ShipDestroyed, UfoDestroyed};
+use crate::settings::Settings;
+use bevy::audio::{play_queued_audio_system, AudioOutput, AudioSink, Decodable, Source};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// every sound is generated when the game starts, at this rate
+const SAMPLE_RATE: u32 = 22050;
+/// seconds between heartbeats with a full and an almost empty field
+const SLOWEST_BEAT: f32 = 1.0;
+const FASTEST_BEAT: f32 = 0.25;
+
+/// The sound effects, synthesized at startup instead of being loaded from files
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        // the same setup bevy's AudioPlugin does for its own AudioSource
+        app.init_non_send_resource::<AudioOutput<Synth>>()
+            .add_asset::<Synth>()
+            .init_resource::<Audio<Synth>>()
+            .add_system_to_stage(CoreStage::PostUpdate, play_queued_audio_system::<Synth>)
+            .insert_resource(Heartbeat {
+                timer: Timer::from_seconds(SLOWEST_BEAT, TimerMode::Once),
+                high: false,
+                peak: 0,
+            })
+            .add_startup_system(create_sounds)
+            .add_system(update_loops)
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_heartbeat))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(play_fire)
+                    .with_system(play_explosions)
+                    .with_system(play_heartbeat),
+            );
+    }
+}
+
+/// A mono sound made of raw samples between -1.0 and 1.0
+#[derive(TypeUuid)]
+#[uuid = "cfe7493a-c713-40c4-a8a3-a340ddb2c3bf"]
+pub struct Synth {
+    samples: Arc<[f32]>,
+}
+
+impl Decodable for Synth {
+    type Decoder = SynthDecoder;
+    type DecoderItem = f32;
+
+    fn decoder(&self) -> Self::Decoder {
+        SynthDecoder {
+            samples: self.samples.clone(),
+            position: 0,
+        }
+    }
+}
+
+/// plays back a [`Synth`]
+pub struct SynthDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(
+            self.samples.len() as f32 / SAMPLE_RATE as f32,
+        ))
+    }
+}
+
+#[derive(Resource)]
+struct Sounds {
+    fire: Handle<Synth>,
+    /// small, medium and large
+    explosions: [Handle<Synth>; 3],
+    /// the two alternating notes of the heartbeat
+    beats: [Handle<Synth>; 2],
+    /// these keep playing for as long as the game runs, and are paused when not needed
+    thrust: Handle<AudioSink>,
+    siren: Handle<AudioSink>,
+}
+
+/// the background beat of the arcade game, speeding up as the wave is cleared
+#[derive(Resource)]
+struct Heartbeat {
+    timer: Timer,
+    /// which of the two notes is next
+    high: bool,
+    /// the most work the current wave has needed; 0 until the wave shows up
+    peak: u32,
+}
+
+impl Heartbeat {
+    /// start slow again for a new game or wave
+    fn reset(&mut self) {
+        self.peak = 0;
+        self.timer = Timer::from_seconds(SLOWEST_BEAT, TimerMode::Once);
+    }
+}
+
+/// Render `seconds` of audio, calling `sample` with the time of each sample
+fn render(seconds: f32, mut sample: impl FnMut(f32) -> f32) -> Synth {
+    let count = (seconds * SAMPLE_RATE as f32) as usize;
+    Synth {
+        samples: (0..count)
+            .map(|i| sample(i as f32 / SAMPLE_RATE as f32).clamp(-1.0, 1.0))
+            .collect(),
+    }
+}
+
+fn square(phase: f32) -> f32 {
+    if phase.fract() < 0.5 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// white noise through a simple low-pass filter; lower `smoothing` is darker
+fn noise(rng: &mut StdRng, smoothing: f32) -> impl FnMut() -> f32 + '_ {
+    let mut last = 0.0;
+    move || {
+        last += (rng.gen_range(-1.0..1.0) - last) * smoothing;
+        last
+    }
+}
+
+/// a short square wave chirp sweeping down
+fn fire_sound() -> Synth {
+    let length = 0.12;
+    let mut phase = 0.0;
+    render(length, |t| {
+        let progress = t / length;
+        phase += (1200.0 - 900.0 * progress) / SAMPLE_RATE as f32;
+        square(phase) * 0.25 * (1.0 - progress)
+    })
+}
+
+/// a rumble of dark noise, made to loop
+fn thrust_sound(rng: &mut StdRng) -> Synth {
+    let mut noise = noise(rng, 0.08);
+    render(0.5, |_| noise() * 0.8)
+}
+
+/// a burst of noise that dies away, longer and darker the bigger the explosion
+fn explosion_sound(rng: &mut StdRng, length: f32, smoothing: f32) -> Synth {
+    let mut noise = noise(rng, smoothing);
+    render(length, |t| {
+        let envelope = (-5.0 * t / length).exp();
+        noise() * envelope * 1.5
+    })
+}
+
+/// a low thump
+fn beat_sound(frequency: f32) -> Synth {
+    let length = 0.12;
+    render(length, |t| {
+        let envelope = 1.0 - t / length;
+        (t * frequency * TAU).sin() * envelope * 0.6
+    })
+}
+
+/// a square wave wobbling up and down, a whole number of times so it loops cleanly
+fn siren_sound() -> Synth {
+    let mut phase = 0.0;
+    render(1.0, |t| {
+        let frequency = 750.0 + 150.0 * (t * 6.0 * TAU).sin();
+        phase += frequency / SAMPLE_RATE as f32;
+        square(phase) * 0.12
+    })
+}
+
+fn create_sounds(
+    mut commands: Commands,
+    mut synths: ResMut<Assets<Synth>>,
+    sinks: Res<Assets<AudioSink>>,
+    audio: Res<Audio<Synth>>,
+) {
+    // always the same sounds, there's no need for them to be random between runs
+    let mut rng = StdRng::seed_from_u64(7);
+    let thrust = synths.add(thrust_sound(&mut rng));
+    let siren = synths.add(siren_sound());
+    let start_loop = |sound| {
+        // silent until update_loops picks a volume and pauses it
+        sinks.get_handle(audio.play_with_settings(sound, PlaybackSettings::LOOP.with_volume(0.0)))
+    };
+
+    commands.insert_resource(Sounds {
+        fire: synths.add(fire_sound()),
+        explosions: [
+            synths.add(explosion_sound(&mut rng, 0.4, 0.5)),
+            synths.add(explosion_sound(&mut rng, 0.8, 0.25)),
+            synths.add(explosion_sound(&mut rng, 1.3, 0.1)),
+        ],
+        beats: [synths.add(beat_sound(55.0)), synths.add(beat_sound(49.0))],
+        thrust: start_loop(thrust),
+        siren: start_loop(siren),
+    });
+}
+
+fn play(audio: &Audio<Synth>, settings: &Settings, sound: &Handle<Synth>) {
+    audio.play_with_settings(
+        sound.clone(),
+        PlaybackSettings::ONCE.with_volume(settings.audio.volume),
+    );
+}
+
+fn play_fire(
+    audio: Res<Audio<Synth>>,
+    settings: Res<Settings>,
+    sounds: Res<Sounds>,
+    mut fired_events: EventReader<LaserFired>,
+) {
+    if fired_events.iter().count() > 0 {
+        play(&audio, &settings, &sounds.fire);
+    }
+}
+
+fn play_explosions(
+    audio: Res<Audio<Synth>>,
+    settings: Res<Settings>,
+    sounds: Res<Sounds>,
+    mut asteroid_events: EventReader<AsteroidDestroyed>,
+    mut ufo_events: EventReader<UfoDestroyed>,
+    mut ship_events: EventReader<ShipDestroyed>,
+) {
+    let [small, medium, large] = &sounds.explosions;
+    for destroyed in asteroid_events.iter() {
+        let sound = match destroyed.size {
+            AsteroidSize::Large => large,
+            AsteroidSize::Medium => medium,
+            AsteroidSize::Small => small,
+        };
+        play(&audio, &settings, sound);
+    }
+    for _ in ufo_events.iter() {
+        play(&audio, &settings, medium);
+    }
+    for _ in ship_events.iter() {
+        play(&audio, &settings, large);
+    }
+}
+
+/// how many hits it takes to clear an asteroid and everything it breaks into
+fn hits_to_clear(size: AsteroidSize) -> u32 {
+    match size {
+        AsteroidSize::Large => 21,
+        AsteroidSize::Medium => 5,
+        AsteroidSize::Small => 1,
+    }
+}
+
+fn reset_heartbeat(mut heartbeat: ResMut<Heartbeat>) {
+    heartbeat.reset();
+}
+
+fn play_heartbeat(
+    time: Res<Time>,
+    audio: Res<Audio<Synth>>,
+    settings: Res<Settings>,
+    sounds: Res<Sounds>,
+    mut heartbeat: ResMut<Heartbeat>,
+    mut cleared_events: EventReader<LevelCleared>,
+    asteroids: Query<&Asteroid>,
+) {
+    if cleared_events.iter().count() > 0 {
+        heartbeat.reset();
+    }
+    let remaining: u32 = asteroids
+        .iter()
+        .map(|asteroid| hits_to_clear(asteroid.0))
+        .sum();
+    if remaining == 0 {
+        return;
+    }
+    heartbeat.peak = heartbeat.peak.max(remaining);
+
+    if heartbeat.timer.tick(time.delta()).finished() {
+        let left = remaining as f32 / heartbeat.peak as f32;
+        let interval = FASTEST_BEAT + (SLOWEST_BEAT - FASTEST_BEAT) * left;
+        heartbeat.timer = Timer::from_seconds(interval, TimerMode::Once);
+
+        play(&audio, &settings, &sounds.beats[heartbeat.high as usize]);
+        heartbeat.high = !heartbeat.high;
+    }
+}
+
+/// Play the thrust rumble while the ship accelerates and the siren while a UFO is
+/// around, both only while the game is being played
+fn update_loops(
+    state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    sounds: Option<Res<Sounds>>,
+    sinks: Res<Assets<AudioSink>>,
+    ships: Query<&ShipInput, With<Ship>>,
+    ufos: Query<(), With<Ufo>>,
+) {
+    let Some(sounds) = sounds else {
+        return;
+    };
+    let playing = *state.current() == GameState::Playing;
+    let thrusting = playing && ships.iter().any(|input| input.thrust);
+    let siren = playing && !ufos.is_empty();
+
+    for (handle, on, volume) in [
+        (&sounds.thrust, thrusting, 0.6),
+        (&sounds.siren, siren, 1.0),
+    ] {
+        // the sinks only show up once the audio output has started them
+        let Some(sink) = sinks.get(handle) else {
+            continue;
+        };
+        if sink.is_paused() == on {
+            sink.toggle();
+        }
+        let volume = volume * settings.audio.volume;
+        if sink.volume() != volume {
+            sink.set_volume(volume);
+        }
+    }
+}
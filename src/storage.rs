@@ -0,0 +1,69 @@
+//! Tiny key/value persistence: a file per key on native builds and
+//! `localStorage` in the browser
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+/// The game's folder in the user's config directory (`~/.config/asteroids` on Linux,
+/// `%APPDATA%\asteroids` on Windows), or next to the executable when there is none,
+/// so the files don't land wherever the game happened to be started from
+#[cfg(not(target_arch = "wasm32"))]
+fn directory() -> PathBuf {
+    dirs::config_dir()
+        .map(|config| config.join("asteroids"))
+        .or_else(|| {
+            let executable = std::env::current_exe().ok()?;
+            Some(executable.parent()?.to_path_buf())
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(key: &str) -> Option<String> {
+    std::fs::read_to_string(directory().join(key)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(key: &str, value: &str) {
+    let directory = directory();
+    let written = std::fs::create_dir_all(&directory)
+        .and_then(|_| std::fs::write(directory.join(key), value));
+    if let Err(error) = written {
+        bevy::log::warn!("could not save {}: {}", key, error);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(key: &str) {
+    match std::fs::remove_file(directory().join(key)) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+            bevy::log::warn!("could not remove {}: {}", key, error);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(key: &str) -> Option<String> {
+    local_storage()?.get_item(key).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(key: &str, value: &str) {
+    let saved = local_storage().map(|storage| storage.set_item(key, value).is_ok());
+    if saved != Some(true) {
+        bevy::log::warn!("could not save {} to local storage", key);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(key: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.remove_item(key);
+    }
+}
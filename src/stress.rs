@@ -0,0 +1,180 @@
+use crate::asteroid::create_asteroid;
+use crate::components::*;
+use crate::difficulty::GameDifficulty;
+use crate::pool::Pool;
+use crate::resources::Arena;
+use crate::settings::launch_flag;
+use crate::ship::LASER_SPEED;
+use bevy::prelude::*;
+use bevy::window::PresentMode;
+use rand::*;
+
+/// how many asteroids are kept on the field
+const FIELD_SIZE: usize = 300;
+/// at most this many are added in a frame, so topping up doesn't cause a spike itself
+const SPAWNS_PER_FRAME: usize = 20;
+const LASERS_PER_FRAME: usize = 4;
+/// how often the frame times are reported
+const REPORT_INTERVAL: f32 = 5.0;
+/// the 99th percentile frame time, in ms, that keeps the game at 60 frames a second.
+/// Vsync is off while stressing, so frames take as long as their work and not a
+/// whole refresh
+const P99_BUDGET: f32 = 1000.0 / 60.0;
+/// the longest single frame, in ms, before it shows as a stutter
+const WORST_BUDGET: f32 = 50.0;
+
+/// Stress mode, started with `--stress` (or `?stress` in the browser): the game goes
+/// straight into play without a ship, keeps a crowded field and sprays lasers all
+/// over it, logging frame times, uncapped by vsync. With pooling these should stay
+/// flat once the pool has grown to fit; a report over budget after the first is
+/// logged as an error
+pub struct StressPlugin;
+
+impl Plugin for StressPlugin {
+    fn build(&self, app: &mut App) {
+        if !launch_flag("stress") {
+            return;
+        }
+        app.insert_resource(FrameTimes::default())
+            .add_startup_system(disable_vsync)
+            .add_system_set(SystemSet::on_update(GameState::Title).with_system(start_playing))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(remove_ship)
+                    .with_system(fill_field)
+                    .with_system(spray_lasers)
+                    .with_system(report_frame_times),
+            );
+    }
+}
+
+/// the frame times seen since the last report
+#[derive(Resource)]
+struct FrameTimes {
+    samples: Vec<f32>,
+    report: Timer,
+    /// the first report covers the pool growing and the shaders compiling
+    warmed_up: bool,
+}
+
+impl Default for FrameTimes {
+    fn default() -> Self {
+        Self {
+            samples: Vec::new(),
+            report: Timer::from_seconds(REPORT_INTERVAL, TimerMode::Repeating),
+            warmed_up: false,
+        }
+    }
+}
+
+fn start_playing(mut state: ResMut<State<GameState>>) {
+    state.set(GameState::Playing).unwrap();
+}
+
+/// nobody is flying, and nothing should end the game
+fn remove_ship(mut commands: Commands, query: Query<Entity, With<Ship>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// top the field up with asteroids of every size anywhere in the arena
+fn fill_field(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    difficulty: Res<GameDifficulty>,
+    arena: Res<Arena>,
+    asteroids: Query<(), With<Asteroid>>,
+) {
+    let mut rng = rand::thread_rng();
+    let missing = FIELD_SIZE.saturating_sub(asteroids.iter().count());
+    let half_extents = arena.half_extents();
+    for _ in 0..missing.min(SPAWNS_PER_FRAME) {
+        let size = match rng.gen_range(0..3) {
+            0 => AsteroidSize::Large,
+            1 => AsteroidSize::Medium,
+            _ => AsteroidSize::Small,
+        };
+        let position = Vec2::new(
+            rng.gen_range(-half_extents.x..half_extents.x),
+            rng.gen_range(-half_extents.y..half_extents.y),
+        );
+        create_asteroid(
+            &mut commands,
+            &mut pool,
+            difficulty.0,
+            &mut rng,
+            size,
+            position.extend(0.0),
+        );
+    }
+}
+
+/// fire from the middle of the arena in a slowly turning fan
+fn spray_lasers(
+    mut commands: Commands,
+    mut pool: ResMut<Pool>,
+    time: Res<Time>,
+    mut angle: Local<f32>,
+) {
+    *angle += time.delta_seconds() * 2.0;
+    for i in 0..LASERS_PER_FRAME {
+        let direction = *angle + i as f32 * std::f32::consts::TAU / LASERS_PER_FRAME as f32;
+        let rotation = Quat::from_rotation_z(direction);
+        pool.fire_laser(
+            &mut commands,
+            Player(0),
+            Transform::from_rotation(rotation),
+            (rotation * Vec3::Y).truncate() * LASER_SPEED,
+        );
+    }
+}
+
+/// waiting for the display would make every frame at least one refresh long
+fn disable_vsync(mut windows: ResMut<Windows>) {
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_present_mode(PresentMode::AutoNoVsync);
+    }
+}
+
+fn report_frame_times(
+    time: Res<Time>,
+    mut frame_times: ResMut<FrameTimes>,
+    pool: Res<Pool>,
+    asteroids: Query<(), With<Asteroid>>,
+    lasers: Query<(), With<Laser>>,
+) {
+    let delta = time.delta_seconds() * 1000.0;
+    frame_times.samples.push(delta);
+    if !frame_times.report.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let mut samples = std::mem::take(&mut frame_times.samples);
+    samples.sort_by(f32::total_cmp);
+    let count = samples.len() as f32;
+    let mean = samples.iter().sum::<f32>() / count;
+    let deviation = (samples.iter().map(|t| (t - mean).powi(2)).sum::<f32>() / count).sqrt();
+    let p99 = samples[((count * 0.99) as usize).min(samples.len() - 1)];
+    let worst = samples[samples.len() - 1];
+    info!(
+        "stress: {} asteroids, {} lasers, {} pooled entities | frame {:.2} ms avg, {:.2} ms deviation, {:.2} ms p99, {:.2} ms worst",
+        asteroids.iter().count(),
+        lasers.iter().count(),
+        pool.built(),
+        mean,
+        deviation,
+        p99,
+        worst
+    );
+
+    if !std::mem::replace(&mut frame_times.warmed_up, true) {
+        return;
+    }
+    if p99 > P99_BUDGET || worst > WORST_BUDGET {
+        error!(
+            "stress: over budget, {:.2} ms p99 (budget {:.2} ms), {:.2} ms worst (budget {:.2} ms)",
+            p99, P99_BUDGET, worst, WORST_BUDGET
+        );
+    }
+}
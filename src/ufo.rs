@@ -0,0 +1,290 @@
+use crate::collision::{DispatchCollisions, UfoHit};
+use crate::components::*;
+use crate::difficulty::GameDifficulty;
+use crate::events::UfoDestroyed;
+use crate::pool;
+use crate::resources::*;
+use crate::settings::Settings;
+use crate::shape_cache::{ShapeCache, ShapeKind};
+use crate::ship::heading;
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::*;
+
+pub const UFO_SPEED: f32 = 150.0;
+pub const ENEMY_LASER_SPEED: f32 = 400.0;
+/// seconds between changes of vertical direction
+pub const TURN_INTERVAL: f32 = 1.5;
+/// how far ahead of the saucer its shots appear
+const MUZZLE: f32 = 25.0;
+/// UFOs and their lasers go this far past the edge before they are gone, so the
+/// saucer can enter from just outside it
+const OFFSCREEN_MARGIN: f32 = 40.0;
+pub const SAUCER_POINTS: [Vec2; 6] = [
+    Vec2::new(-20.0, 0.0),
+    Vec2::new(-8.0, 7.0),
+    Vec2::new(8.0, 7.0),
+    Vec2::new(20.0, 0.0),
+    Vec2::new(8.0, -7.0),
+    Vec2::new(-8.0, -7.0),
+];
+
+pub struct UfoPlugin;
+
+impl Plugin for UfoPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(UfoSpawnTimer(Timer::from_seconds(1.0, TimerMode::Once)))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(reset_ufo_timer))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_ufo)
+                    .with_system(steer_ufo)
+                    .with_system(ufo_fire)
+                    .with_system(destroy_ufo.after(DispatchCollisions))
+                    .with_system(remove_offscreen),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_ufos));
+    }
+}
+
+/// counts down to the next UFO appearance
+#[derive(Resource)]
+struct UfoSpawnTimer(Timer);
+
+/// more aggressive saucers come around more often
+pub fn spawn_interval(aggression: f32) -> f32 {
+    30.0 - 20.0 * aggression
+}
+
+/// and shoot more often
+fn fire_interval(aggression: f32) -> f32 {
+    2.0 - 1.3 * aggression
+}
+
+impl Ufo {
+    pub fn new(aggression: f32) -> Self {
+        Self {
+            fire_timer: Timer::from_seconds(fire_interval(aggression), TimerMode::Repeating),
+            turn_timer: Timer::from_seconds(TURN_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+pub fn saucer_collider() -> Collider {
+    Collider::polyline(SAUCER_POINTS.to_vec(), None)
+}
+
+pub fn bullet_collider() -> Collider {
+    Collider::cuboid(1.5, 1.5)
+}
+
+/// Where a saucer enters an arena of `half_extents`, from a random side at a random
+/// height, and how fast it goes
+pub fn entry(rng: &mut impl Rng, half_extents: Vec2) -> (Vec2, Vec2) {
+    let direction = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+    let position = Vec2::new(
+        -direction * (half_extents.x + 20.0),
+        rng.gen_range(-half_extents.y * 0.8..half_extents.y * 0.8),
+    );
+    (position, Vec2::new(direction * UFO_SPEED, 0.0))
+}
+
+/// The saucer's new vertical speed, when it is at height `y` going `vy` and kept
+/// within `limit` of the middle. It turns back at the limit, and otherwise picks a
+/// new direction whenever its turn timer has just `turned`
+pub fn steer(rng: &mut impl Rng, y: f32, vy: f32, limit: f32, turned: bool) -> f32 {
+    if y.abs() > limit && vy * y > 0.0 {
+        -vy
+    } else if turned {
+        [-1.0, 0.0, 1.0][rng.gen_range(0..3)] * UFO_SPEED * 0.5
+    } else {
+        vy
+    }
+}
+
+/// Which way a saucer at `origin` shoots: at `target` when there is a ship, missing
+/// by less the more aggressive it is, and anywhere when there isn't
+pub fn aim(rng: &mut impl Rng, origin: Vec2, target: Option<Vec2>, aggression: f32) -> Vec2 {
+    let aim = match target {
+        Some(target) => (target - origin).normalize_or_zero(),
+        None => heading(rng.gen_range(0.0..std::f32::consts::TAU)),
+    };
+    let max_error = (1.0 - aggression) * 0.6 + 0.05;
+    heading(rng.gen_range(-max_error..max_error)).rotate(aim)
+}
+
+/// where a shot fired in `direction` by a saucer at `origin` appears
+pub fn muzzle(origin: Vec2, direction: Vec2) -> Vec2 {
+    origin + direction * MUZZLE
+}
+
+/// whether a saucer or a shot at `position` has left an arena of `half_extents`
+pub fn offscreen(position: Vec2, half_extents: Vec2) -> bool {
+    let bounds = half_extents + OFFSCREEN_MARGIN;
+    position.x.abs() > bounds.x || position.y.abs() > bounds.y
+}
+
+fn reset_ufo_timer(difficulty: Res<GameDifficulty>, mut spawn_timer: ResMut<UfoSpawnTimer>) {
+    let aggression = difficulty.preset().ufo_aggression;
+    spawn_timer.0 = Timer::from_seconds(spawn_interval(aggression), TimerMode::Once);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_ufo(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    difficulty: Res<GameDifficulty>,
+    time: GameTime,
+    arena: Res<Arena>,
+    mode: Res<GameMode>,
+    mut rng: ResMut<GameRng>,
+    mut spawn_timer: ResMut<UfoSpawnTimer>,
+    ufos: Query<(), With<Ufo>>,
+) {
+    // versus is between the players, and the sandbox is left to whoever is in it
+    if matches!(*mode, GameMode::Versus | GameMode::Sandbox) {
+        return;
+    }
+    // only one saucer at a time, and the clock doesn't run while one is around
+    if !ufos.is_empty() || !spawn_timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    let aggression = difficulty.preset().ufo_aggression;
+    spawn_timer.0 = Timer::from_seconds(spawn_interval(aggression), TimerMode::Once);
+
+    // from the game's numbers so a seeded run gets the same saucers
+    let (position, velocity) = entry(&mut rng.rng, arena.half_extents());
+    let saucer = shapes::Polygon {
+        points: SAUCER_POINTS.to_vec(),
+        closed: true,
+    };
+
+    commands
+        .spawn(GeometryBuilder::build_as(
+            &saucer,
+            settings.draw_mode(),
+            Transform::from_translation(position.extend(0.0)),
+        ))
+        .insert(RigidBody::Dynamic)
+        .insert(GravityScale(0.0))
+        .insert(saucer_collider())
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(Sensor)
+        .insert(Velocity {
+            linvel: velocity,
+            ..default()
+        })
+        .insert(Sleeping::disabled())
+        .insert(Ccd::enabled())
+        .insert(Dimensions {
+            width: 40.0,
+            height: 14.0,
+        })
+        .insert(Ufo::new(aggression));
+}
+
+/// zig-zag up and down while crossing the screen, without leaving it
+fn steer_ufo(
+    time: GameTime,
+    arena: Res<Arena>,
+    mut rng: ResMut<GameRng>,
+    mut query: Query<(&mut Ufo, &mut Velocity, &Transform)>,
+) {
+    let limit = arena.half_extents().y * 0.8;
+    for (mut ufo, mut velocity, transform) in query.iter_mut() {
+        let turned = ufo.turn_timer.tick(time.delta()).just_finished();
+        velocity.linvel.y = steer(
+            &mut rng.rng,
+            transform.translation.y,
+            velocity.linvel.y,
+            limit,
+            turned,
+        );
+    }
+}
+
+/// shoot at the ship, missing by less the more aggressive the saucer is
+fn ufo_fire(
+    mut commands: Commands,
+    difficulty: Res<GameDifficulty>,
+    shapes: Res<ShapeCache>,
+    time: GameTime,
+    mut rng: ResMut<GameRng>,
+    mut ufos: Query<(&mut Ufo, &Transform)>,
+    ship: Query<&Transform, With<Ship>>,
+) {
+    let aggression = difficulty.preset().ufo_aggression;
+    let rng = &mut rng.rng;
+    for (mut ufo, transform) in ufos.iter_mut() {
+        if !ufo.fire_timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let origin = transform.translation.truncate();
+        let target = ship
+            .iter()
+            .next()
+            .map(|target| target.translation.truncate());
+        let direction = aim(rng, origin, target, aggression);
+
+        commands
+            .spawn(shapes.bundle(
+                ShapeKind::Bullet,
+                Transform::from_translation(muzzle(origin, direction).extend(0.0)),
+            ))
+            .insert(RigidBody::Dynamic)
+            .insert(GravityScale(0.0))
+            .insert(bullet_collider())
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(Sensor)
+            .insert(Velocity {
+                linvel: direction * ENEMY_LASER_SPEED,
+                ..default()
+            })
+            .insert(Ccd::enabled())
+            .insert(Sleeping::disabled())
+            .insert(EnemyLaser);
+    }
+}
+
+/// the player's lasers can shoot the UFO down
+fn destroy_ufo(
+    mut commands: Commands,
+    mut hits: EventReader<UfoHit>,
+    mut destroyed_events: EventWriter<UfoDestroyed>,
+    ufos: Query<(&Transform, &Velocity), With<Ufo>>,
+) {
+    for hit in hits.iter() {
+        let Ok((transform, velocity)) = ufos.get(hit.ufo) else {
+            continue;
+        };
+        commands.entity(hit.ufo).despawn();
+        pool::release(&mut commands, hit.laser);
+        destroyed_events.send(UfoDestroyed {
+            position: transform.translation.truncate(),
+            velocity: velocity.linvel,
+            by: hit.by,
+        });
+    }
+}
+
+/// UFOs and their lasers don't wrap; they are gone once they leave the screen
+#[allow(clippy::type_complexity)]
+fn remove_offscreen(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    query: Query<(Entity, &Transform), Or<(With<Ufo>, With<EnemyLaser>)>>,
+) {
+    for (entity, transform) in query.iter() {
+        if offscreen(transform.translation.truncate(), arena.half_extents()) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn remove_ufos(mut commands: Commands, query: Query<Entity, Or<(With<Ufo>, With<EnemyLaser>)>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
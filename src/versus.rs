@@ -0,0 +1,170 @@
+use crate::components::*;
+use crate::events::ShipDestroyed;
+use crate::pool;
+use crate::resources::{GameMode, Players, Rounds};
+use crate::ship::{destroy_ship, lose_life, respawn_ship};
+use bevy::prelude::*;
+
+/// kills that win a round
+const KILLS_PER_ROUND: u32 = 3;
+/// rounds that win the match
+const ROUNDS_TO_WIN: u32 = 2;
+
+/// Versus: the two ships can shoot each other down. A player's score is the kills
+/// in the current round; the first to `KILLS_PER_ROUND` takes the round and both
+/// ships start over, and whoever takes `ROUNDS_TO_WIN` rounds wins the match.
+/// Running into an asteroid doesn't count for anyone, it just costs time
+pub struct VersusPlugin;
+
+impl Plugin for VersusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_match))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(
+                    // once the ship is gone and its respawn started, and before any
+                    // respawn is due, so the new round's start isn't left to chance
+                    count_kills
+                        .after(destroy_ship)
+                        .after(lose_life)
+                        .before(respawn_ship),
+                ),
+            );
+    }
+}
+
+/// What a kill did to the match
+#[derive(Debug, PartialEq, Eq)]
+pub enum Kill {
+    Counted,
+    /// the killer took the round, and everyone starts the next one from scratch
+    RoundWon,
+    /// the killer won the match
+    MatchWon,
+}
+
+/// credit `killer` with a kill, finishing the round or the match when that is enough
+pub fn count_kill(players: &mut Players, rounds: &mut Rounds, killer: Player) -> Kill {
+    let Some(player) = players.get_mut(killer) else {
+        return Kill::Counted;
+    };
+    player.score += 1;
+    if player.score < KILLS_PER_ROUND {
+        return Kill::Counted;
+    }
+    rounds.wins[killer.0] += 1;
+    if rounds.wins[killer.0] >= ROUNDS_TO_WIN {
+        return Kill::MatchWon;
+    }
+    rounds.number += 1;
+    players.start_round();
+    Kill::RoundWon
+}
+
+fn start_match(mode: Res<GameMode>, mut rounds: ResMut<Rounds>) {
+    *rounds = Rounds::new(mode.players());
+}
+
+/// Credit the shooter with each kill, and finish the round (or the match) when
+/// someone has enough
+#[allow(clippy::too_many_arguments)]
+fn count_kills(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut players: ResMut<Players>,
+    mut rounds: ResMut<Rounds>,
+    mut state: ResMut<State<GameState>>,
+    mut destroyed_events: EventReader<ShipDestroyed>,
+    ships: Query<(Entity, &Player), With<Ship>>,
+    lasers: Query<Entity, With<Laser>>,
+) {
+    if *mode != GameMode::Versus {
+        return;
+    }
+    for destroyed in destroyed_events.iter() {
+        let Some(killer) = destroyed.by else {
+            continue;
+        };
+        match count_kill(&mut players, &mut rounds, killer) {
+            Kill::Counted => continue,
+            Kill::MatchWon => {
+                state.set(GameState::GameOver).unwrap();
+                return;
+            }
+            Kill::RoundWon => {}
+        }
+        // the ship just shot down is already on its way out
+        for (ship, _) in ships
+            .iter()
+            .filter(|(_, player)| **player != destroyed.player)
+        {
+            commands.entity(ship).despawn_recursive();
+        }
+        for laser in lasers.iter() {
+            pool::release(&mut commands, laser);
+        }
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_match() -> (Players, Rounds) {
+        (Players::new(2, 1), Rounds::new(2))
+    }
+
+    #[test]
+    fn kills_short_of_a_round_are_counted() {
+        let (mut players, mut rounds) = new_match();
+        for kills in 1..KILLS_PER_ROUND {
+            assert_eq!(
+                count_kill(&mut players, &mut rounds, Player(1)),
+                Kill::Counted
+            );
+            assert_eq!(players.0[1].score, kills);
+        }
+        assert_eq!((rounds.number, &rounds.wins[..]), (1, &[0, 0][..]));
+    }
+
+    #[test]
+    fn enough_kills_take_the_round_and_start_another() {
+        let (mut players, mut rounds) = new_match();
+        players.0[1].score = 1;
+        for _ in 1..KILLS_PER_ROUND {
+            count_kill(&mut players, &mut rounds, Player(0));
+        }
+        assert_eq!(
+            count_kill(&mut players, &mut rounds, Player(0)),
+            Kill::RoundWon
+        );
+        assert_eq!((rounds.number, &rounds.wins[..]), (2, &[1, 0][..]));
+        // everyone starts over, and comes back in a moment
+        assert!(players
+            .0
+            .iter()
+            .all(|player| player.score == 0 && player.respawn.is_some()));
+    }
+
+    #[test]
+    fn enough_rounds_win_the_match() {
+        let (mut players, mut rounds) = new_match();
+        rounds.wins[1] = ROUNDS_TO_WIN - 1;
+        players.0[1].score = KILLS_PER_ROUND - 1;
+        assert_eq!(
+            count_kill(&mut players, &mut rounds, Player(1)),
+            Kill::MatchWon
+        );
+        assert_eq!(rounds.wins[1], ROUNDS_TO_WIN);
+    }
+
+    #[test]
+    fn nobody_else_gets_the_kill() {
+        let (mut players, mut rounds) = new_match();
+        assert_eq!(
+            count_kill(&mut players, &mut rounds, Player(5)),
+            Kill::Counted
+        );
+        assert!(players.0.iter().all(|player| player.score == 0));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::components::*;
+use crate::resources::Arena;
+use bevy::prelude::*;
+use bevy::sprite::Mesh2dHandle;
+use bevy::utils::HashMap;
+use bevy_prototype_lyon::render::Shape;
+use bevy_rapier2d::prelude::*;
+
+/// Toroidal playfield: wrappers teleport to the other side as soon as their center
+/// crosses an edge, and while they straddle an edge a ghost copy of them is drawn
+/// (and can be hit) on the opposite side
+pub struct WrapPlugin;
+
+impl Plugin for WrapPlugin {
+    fn build(&self, app: &mut App) {
+        for state in [GameState::Title, GameState::Playing] {
+            app.add_system_set(
+                SystemSet::on_update(state)
+                    .with_system(wrap_mover)
+                    .with_system(update_ghosts.after(wrap_mover)),
+            );
+        }
+        // after the frame's despawns have been applied, so nothing is despawned twice
+        app.add_system_to_stage(CoreStage::PostUpdate, remove_orphan_ghosts);
+    }
+}
+
+/// Resolve a collider to the entity it stands for, so collision handlers treat a hit
+/// on a ghost as a hit on its owner
+pub fn owner_of(entity: Entity, ghosts: &Query<&Ghost>) -> Entity {
+    ghosts.get(entity).map_or(entity, |ghost| ghost.owner)
+}
+
+/// Wrap the ship/asteroids around the arena when their center goes off the edge
+fn wrap_mover(arena: Res<Arena>, mut query: Query<&mut Transform, With<Wrapper>>) {
+    let half_extents = arena.half_extents();
+    for mut transform in query.iter_mut() {
+        let position = wrap_position(transform.translation.truncate(), half_extents);
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+/// Bring a position that went over an edge of an arena of `half_extents` back in on
+/// the opposite side. World coordinates are in the center, so the edges are at
+/// plus and minus the half extents
+pub fn wrap_position(mut position: Vec2, half_extents: Vec2) -> Vec2 {
+    let size = half_extents * 2.0;
+    if position.x > half_extents.x {
+        position.x -= size.x;
+    } else if position.x < -half_extents.x {
+        position.x += size.x;
+    }
+
+    if position.y > half_extents.y {
+        position.y -= size.y;
+    } else if position.y < -half_extents.y {
+        position.y += size.y;
+    }
+    position
+}
+
+/// Where `position`, or the ghost of whatever is there, is closest to `from`. Taking
+/// that one stands in for the ghosts when there are no entities to check against
+pub fn nearest_image(position: Vec2, from: Vec2, half_extents: Vec2) -> Vec2 {
+    let size = half_extents * 2.0;
+    let offset = position - from;
+    position - size * (offset / size).round()
+}
+
+/// Spawn, move and remove the ghosts of every wrapper so that each part of it that
+/// sticks out past an edge shows up on the other side
+#[allow(clippy::type_complexity)]
+fn update_ghosts(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    owners: Query<(Entity, &Transform, &Dimensions, &Collider, &Mesh2dHandle), With<Wrapper>>,
+    mut ghosts: Query<(Entity, &Ghost, &mut Transform, &mut Mesh2dHandle), Without<Wrapper>>,
+) {
+    let half_extents = arena.half_extents();
+    let mut existing: HashMap<(Entity, BVec2), Entity> = ghosts
+        .iter()
+        .map(|(entity, ghost, ..)| ((ghost.owner, ghost.axes), entity))
+        .collect();
+
+    for (owner, transform, dimensions, collider, mesh) in owners.iter() {
+        let position = transform.translation.truncate();
+        // the shape can be rotated, so allow for its diagonal in every direction
+        let reach = Vec2::new(dimensions.width, dimensions.height).length() / 2.0
+            * transform.scale.truncate().max_element();
+        let near_edge = position.abs() + reach;
+        let near_x = near_edge.x > half_extents.x;
+        let near_y = near_edge.y > half_extents.y;
+        // jump to the opposite side of whichever edge is closest
+        let shift = -position.signum() * Vec2::new(arena.width, arena.height);
+
+        for axes in [
+            BVec2::new(true, false),
+            BVec2::new(false, true),
+            BVec2::TRUE,
+        ] {
+            if (axes.x && !near_x) || (axes.y && !near_y) {
+                continue;
+            }
+            let offset = Vec2::select(axes, shift, Vec2::ZERO);
+            let ghost_transform = Transform {
+                translation: transform.translation + offset.extend(0.0),
+                ..*transform
+            };
+
+            match existing
+                .remove(&(owner, axes))
+                .and_then(|entity| ghosts.get_mut(entity).ok())
+            {
+                Some((_, _, mut transform, mut ghost_mesh)) => {
+                    *transform = ghost_transform;
+                    // the owner's mesh is rebuilt when its draw mode changes
+                    if ghost_mesh.0 != mesh.0 {
+                        *ghost_mesh = mesh.clone();
+                    }
+                }
+                None => {
+                    commands
+                        .spawn(SpatialBundle::from_transform(ghost_transform))
+                        .insert(Shape)
+                        .insert(mesh.clone())
+                        .insert(RigidBody::KinematicPositionBased)
+                        .insert(collider.clone())
+                        .insert(ActiveEvents::COLLISION_EVENTS)
+                        .insert(Sensor)
+                        .insert(Ghost { owner, axes });
+                }
+            }
+        }
+    }
+
+    // whatever is left belongs to an owner that moved away from the edge or is gone
+    for entity in existing.into_values() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// ghosts are only updated while wrappers move; this cleans up after owners that are
+/// despawned when leaving a state
+fn remove_orphan_ghosts(
+    mut commands: Commands,
+    ghosts: Query<(Entity, &Ghost)>,
+    owners: Query<(), With<Wrapper>>,
+) {
+    for (entity, ghost) in ghosts.iter() {
+        if owners.get(ghost.owner).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+}